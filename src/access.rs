@@ -0,0 +1,226 @@
+use std::collections::{BTreeSet, HashMap};
+use petgraph::stable_graph::NodeIndex;
+use petgraph::visit::EdgeRef;
+use primitive_types::U256;
+use revm::opcode::*;
+use crate::dataflow::StackAnalysis;
+use crate::op::Operation;
+use crate::stack::{AbstractValue, SymbolicStack, SymbolicStackValue};
+use crate::dispatcher::aborts;
+use crate::edge::EdgeKind;
+use crate::{BlockKind, Program};
+
+/// EIP-1967 proxy slots: `keccak256("eip1967.proxy.<name>") - 1`.
+pub const EIP1967_IMPLEMENTATION_SLOT: &str = "360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc";
+pub const EIP1967_ADMIN_SLOT: &str = "b53127684a568b3173ae13b9f8a6016e243e63b6e8ee1178d6a717850b5d6103";
+pub const EIP1967_BEACON_SLOT: &str = "a3f0ad74e5423aebfd80d3ef4346578335a9a72aeaee59ff6cb3582b35133d50";
+
+/// An operation only the contract's owner should reach.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum PrivilegedOp {
+    Selfdestruct,
+    Delegatecall,
+    /// SSTORE to one of the EIP-1967 slots.
+    ProxySlotWrite(U256),
+}
+
+/// What CALLER was compared against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Owner {
+    /// A value read with SLOAD, from this slot if it is a constant.
+    Storage(Option<U256>),
+    /// An address hard-coded in the contract.
+    Constant(U256),
+}
+
+/// A JUMPI whose condition compares CALLER against an owner, and whose
+/// branch for any other caller aborts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Guard {
+    pub pc: U256,
+    pub owner: Owner,
+}
+
+/// A privileged operation in an external function, and the closest guard
+/// dominating it, if any.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct AccessControl {
+    /// `None` for the fallback and receive functions.
+    pub selector: Option<[u8; 4]>,
+    pub pc: U256,
+    pub operation: PrivilegedOp,
+    pub guard: Option<Guard>,
+}
+
+impl AccessControl {
+    pub fn is_guarded(&self) -> bool {
+        self.guard.is_some()
+    }
+}
+
+/// A stack value in the default domain, plus what it says about the caller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AuthValue {
+    pub value: SymbolicStackValue,
+    /// Derived from CALLER.
+    pub caller: bool,
+    /// Derived from an owner that is not the caller.
+    pub owner: Option<Owner>,
+    /// Derived from a comparison of the caller against `owner`.
+    pub check: Option<CallerCheck>,
+}
+
+/// A comparison of the caller against an owner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CallerCheck {
+    pub owner: Owner,
+    /// Whether the value is non-zero when the caller is the owner, as for
+    /// `EQ`, rather than when it is not, as for `ISZERO(EQ)`.
+    pub matches: bool,
+}
+
+impl AuthValue {
+    fn with_value(value: SymbolicStackValue) -> Self {
+        AuthValue { value, caller: false, owner: None, check: None }
+    }
+
+    /// What this stands for when compared against the caller.
+    fn as_owner(&self) -> Option<Owner> {
+        self.owner.or_else(|| self.value.as_u256().filter(|value| !value.is_zero()).map(Owner::Constant))
+    }
+}
+
+impl AbstractValue for AuthValue {
+    fn unknown() -> Self {
+        AuthValue::with_value(SymbolicStackValue::Unknown)
+    }
+
+    fn uninitialized() -> Self {
+        AuthValue::with_value(SymbolicStackValue::Uninitialized)
+    }
+
+    fn constant(bytes: &[u8]) -> Self {
+        AuthValue::with_value(SymbolicStackValue::constant(bytes))
+    }
+
+    fn join(&self, other: &Self) -> Self {
+        AuthValue {
+            value: self.value.join(&other.value),
+            caller: self.caller || other.caller,
+            owner: Some(self.owner).filter(|owner| *owner == other.owner).flatten(),
+            check: Some(self.check).filter(|check| *check == other.check).flatten(),
+        }
+    }
+
+    fn transfer(op: &Operation, args: &[Self]) -> Self {
+        let values = args.iter().map(|arg| arg.value).collect::<Vec<_>>();
+        let mut result = AuthValue::with_value(SymbolicStackValue::transfer(op, &values));
+        match op.code.u8() {
+            CALLER => result.caller = true,
+            SLOAD => result.owner = Some(Owner::Storage(args.first().and_then(|slot| slot.value.as_u256()))),
+            EQ => {
+                if let [a, b] = args {
+                    result.check = match (a.caller, b.caller) {
+                        (true, false) => b.as_owner(),
+                        (false, true) => a.as_owner(),
+                        _ => None,
+                    }.map(|owner| CallerCheck { owner, matches: true });
+                }
+            },
+            // Masks and shifts keep what a value was derived from; only a
+            // mask or a negation keeps a check, the latter flipped.
+            _ => {
+                result.caller = args.iter().any(|arg| arg.caller);
+                result.owner = args.iter().find_map(|arg| arg.owner);
+                result.check = match op.code.u8() {
+                    ISZERO => args.first().and_then(|arg| arg.check).map(|check| CallerCheck { matches: !check.matches, ..check }),
+                    AND => args.iter().find_map(|arg| arg.check),
+                    _ => None,
+                };
+            },
+        }
+        result
+    }
+
+    fn as_u256(&self) -> Option<U256> {
+        self.value.as_u256()
+    }
+}
+
+/// Forward propagation of `AuthValue` stacks.
+pub type CallerChecks = StackAnalysis<SymbolicStack<AuthValue>>;
+
+fn proxy_slots() -> [U256; 3] {
+    [EIP1967_IMPLEMENTATION_SLOT, EIP1967_ADMIN_SLOT, EIP1967_BEACON_SLOT].map(|slot| U256::from_str_radix(slot, 16).unwrap())
+}
+
+/// SELFDESTRUCTs, DELEGATECALLs and EIP-1967 slot writes in every external
+/// function and the fallback, each with the nearest dominating JUMPI that
+/// compares CALLER against a storage-loaded or constant address, goes on
+/// towards the operation when they match and aborts otherwise.
+pub fn access_control(program: &Program) -> Vec<AccessControl> {
+    let results = program.solve(&CallerChecks::new());
+    let slots = proxy_slots();
+    // Each guard with the successor taken when the caller matches.
+    let mut guards: HashMap<NodeIndex, (Guard, NodeIndex)> = HashMap::new();
+    let mut sites: Vec<(NodeIndex, U256, PrivilegedOp)> = vec![];
+    program.block_nodes().for_each(|node| {
+        let ops = &program.cfg[node].ops;
+        results.at_ops(&CallerChecks::new(), program, node).into_iter().zip(ops).for_each(|((pc, stack), op)| {
+            let stack = match stack {
+                Some(stack) => stack,
+                None => return,
+            };
+            let operation = match op.code.u8() {
+                SELFDESTRUCT => Some(PrivilegedOp::Selfdestruct),
+                DELEGATECALL => Some(PrivilegedOp::Delegatecall),
+                SSTORE => stack.peek_at(0).as_u256().filter(|slot| slots.contains(slot)).map(PrivilegedOp::ProxySlotWrite),
+                JUMPI => {
+                    let branches = stack.peek_at(1).check.zip(branches(program, node));
+                    if let Some((check, (taken, fallthrough))) = branches {
+                        let (matched, other) = if check.matches { (taken, fallthrough) } else { (fallthrough, taken) };
+                        if aborts(program, other) {
+                            guards.insert(node, (Guard { pc, owner: check.owner }, matched));
+                        }
+                    }
+                    None
+                },
+                _ => None,
+            };
+            sites.extend(operation.map(|operation| (node, pc, operation)));
+        });
+    });
+
+    let dominance = program.dominance();
+    let dispatcher = program.dispatcher();
+    let functions = dispatcher.functions.iter()
+        .map(|function| (Some(function.selector), function.blocks.clone()))
+        .chain(std::iter::once((None, dispatcher.fallback_blocks(program))));
+    let mut findings = BTreeSet::new();
+    functions.for_each(|(selector, blocks)| {
+        sites.iter().filter(|(node, _, _)| blocks.contains(node)).for_each(|(node, pc, operation)| {
+            let guard = std::iter::successors(dominance.immediate_dominator(*node), |dom| dominance.immediate_dominator(*dom))
+                .find_map(|dom| guards.get(&dom).filter(|(_, matched)| dominance.dominates(*matched, *node)))
+                .map(|(guard, _)| *guard);
+            findings.insert(AccessControl {
+                selector,
+                pc: *pc,
+                operation: *operation,
+                guard,
+            });
+        });
+    });
+    findings.into_iter().collect()
+}
+
+/// The taken and fallthrough successors of the JUMPI ending `node`, if each
+/// is a single code block.
+fn branches(program: &Program, node: NodeIndex) -> Option<(NodeIndex, NodeIndex)> {
+    let successor = |kind: EdgeKind| {
+        let mut nodes = program.cfg.edges(node)
+            .filter(|edge| edge.weight().kind == kind && program.cfg[edge.target()].kind == BlockKind::Code)
+            .map(|edge| edge.target());
+        nodes.next().filter(|_| nodes.next().is_none())
+    };
+    successor(EdgeKind::JumpITaken).zip(successor(EdgeKind::JumpIFallthrough))
+}
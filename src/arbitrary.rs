@@ -0,0 +1,136 @@
+use std::collections::BTreeSet;
+use primitive_types::U256;
+use revm::opcode::*;
+use crate::dataflow::StackAnalysis;
+use crate::op::Operation;
+use crate::stack::{AbstractValue, SymbolicStack, SymbolicStackValue};
+use crate::Program;
+
+/// An operand the caller should never pick.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ControlledSiteKind {
+    /// Target of a JUMP or JUMPI.
+    JumpTarget,
+    /// Slot of an SSTORE.
+    StorageSlot,
+}
+
+/// A jump target or storage slot computed from calldata.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ControlledSite {
+    pub kind: ControlledSiteKind,
+    pub pc: U256,
+    pub block: U256,
+    /// Constant offsets of the CALLDATALOADs involved; empty if they were
+    /// all computed.
+    pub calldata_offsets: BTreeSet<U256>,
+    /// Whether the calldata went through a mask, shift or modulus, leaving
+    /// the caller only part of the range.
+    pub masked: bool,
+}
+
+/// A stack value in the default domain, plus how much of it calldata decides.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CalldataValue {
+    pub value: SymbolicStackValue,
+    pub controlled: bool,
+    pub offsets: BTreeSet<U256>,
+    pub masked: bool,
+}
+
+impl CalldataValue {
+    fn with_value(value: SymbolicStackValue) -> Self {
+        CalldataValue { value, controlled: false, offsets: BTreeSet::new(), masked: false }
+    }
+}
+
+impl AbstractValue for CalldataValue {
+    fn unknown() -> Self {
+        CalldataValue::with_value(SymbolicStackValue::Unknown)
+    }
+
+    fn uninitialized() -> Self {
+        CalldataValue::with_value(SymbolicStackValue::Uninitialized)
+    }
+
+    fn constant(bytes: &[u8]) -> Self {
+        CalldataValue::with_value(SymbolicStackValue::constant(bytes))
+    }
+
+    fn join(&self, other: &Self) -> Self {
+        CalldataValue {
+            value: self.value.join(&other.value),
+            controlled: self.controlled || other.controlled,
+            offsets: self.offsets.union(&other.offsets).copied().collect(),
+            masked: self.masked || other.masked,
+        }
+    }
+
+    fn transfer(op: &Operation, args: &[Self]) -> Self {
+        let values = args.iter().map(|arg| arg.value).collect::<Vec<_>>();
+        let mut result = CalldataValue::with_value(SymbolicStackValue::transfer(op, &values));
+        let controlled = args.iter().filter(|arg| arg.controlled);
+        match op.code.u8() {
+            CALLDATALOAD => {
+                result.controlled = true;
+                result.offsets.extend(args.first().and_then(|offset| offset.value.as_u256()));
+            },
+            // The whole range stays reachable.
+            ADD | SUB | XOR | NOT => controlled.for_each(|arg| {
+                result.controlled = true;
+                result.offsets.extend(&arg.offsets);
+                result.masked |= arg.masked;
+            }),
+            // Only part of it does.
+            AND | OR | MUL | DIV | SDIV | MOD | SMOD | ADDMOD | MULMOD | EXP | SIGNEXTEND | BYTE | SHL | SHR | SAR => {
+                controlled.for_each(|arg| {
+                    result.controlled = true;
+                    result.offsets.extend(&arg.offsets);
+                    result.masked = true;
+                });
+            },
+            // Hashes, comparisons and loads no longer let the caller pick
+            // the value; a mapping keyed by calldata is not an arbitrary slot.
+            _ => {},
+        }
+        result
+    }
+
+    fn as_u256(&self) -> Option<U256> {
+        self.value.as_u256()
+    }
+}
+
+/// Forward propagation of `CalldataValue` stacks.
+pub type CalldataControl = StackAnalysis<SymbolicStack<CalldataValue>>;
+
+/// JUMP/JUMPI targets and SSTORE slots that calldata decides, fully or in
+/// part.
+pub fn controlled_sites(program: &Program) -> Vec<ControlledSite> {
+    let results = program.solve(&CalldataControl::new());
+    let mut sites = BTreeSet::new();
+    program.block_nodes().for_each(|node| {
+        let block = &program.cfg[node];
+        results.at_ops(&CalldataControl::new(), program, node).into_iter().zip(&block.ops).for_each(|((pc, stack), op)| {
+            let kind = match op.code.u8() {
+                JUMP | JUMPI => ControlledSiteKind::JumpTarget,
+                SSTORE => ControlledSiteKind::StorageSlot,
+                _ => return,
+            };
+            let operand = match stack {
+                Some(stack) => stack.peek_at(0),
+                None => return,
+            };
+            if operand.controlled {
+                sites.insert(ControlledSite {
+                    kind,
+                    pc,
+                    block: block.id(),
+                    calldata_offsets: operand.offsets,
+                    masked: operand.masked,
+                });
+            }
+        });
+    });
+    sites.into_iter().collect()
+}
@@ -0,0 +1,69 @@
+use std::ops::Range;
+use primitive_types::U256;
+use revm::opcode::*;
+use crate::coverage::constant_codecopies;
+use crate::Program;
+
+/// A contract deployed from initcode embedded in its parent's code.
+#[derive(Debug)]
+pub struct ChildContract {
+    /// pc of the parent's CREATE or CREATE2.
+    pub create_pc: U256,
+    /// CREATE or CREATE2.
+    pub opcode: u8,
+    /// pc of the parent's CODECOPY loading the initcode.
+    pub codecopy_pc: U256,
+    /// Bytes of the parent's code holding the initcode.
+    pub initcode: Range<usize>,
+    /// The initcode, analyzed as a program of its own.
+    pub constructor: Program,
+    /// The code the constructor copies out and returns, if it could be found.
+    pub runtime: Option<Program>,
+}
+
+/// Links every CREATE/CREATE2 to the nearest CODECOPY of a constant range
+/// before it, in its own block or a dominating one, and analyzes that range
+/// as the child's initcode. Children of children are found the same way
+/// through their own `Program::children`.
+pub fn child_contracts(program: &Program) -> Vec<ChildContract> {
+    let dominance = program.dominance();
+    program.block_nodes().flat_map(|node| {
+        let block = &program.cfg[node];
+        block.ops.iter()
+            .filter(|op| [CREATE, CREATE2].contains(&op.code.u8()))
+            .filter_map(|create| {
+                let create_pc = create.pc?;
+                let own = constant_codecopies(program, node).into_iter()
+                    .filter(|(pc, _)| *pc < create_pc)
+                    .last();
+                let (codecopy_pc, initcode) = own.or_else(|| {
+                    std::iter::successors(dominance.immediate_dominator(node), |dom| dominance.immediate_dominator(*dom))
+                        .find_map(|dom| constant_codecopies(program, dom).into_iter().last())
+                })?;
+                // A program copying all of itself would recurse forever.
+                if initcode.len() >= program.code.len() {
+                    return None;
+                }
+                let constructor = analyze(program.code[initcode.clone()].to_vec());
+                let runtime = constructor.block_nodes()
+                    .flat_map(|node| constant_codecopies(&constructor, node))
+                    .max_by_key(|(_, range)| range.len())
+                    .map(|(_, range)| analyze(constructor.code[range].to_vec()));
+                Some(ChildContract {
+                    create_pc,
+                    opcode: create.code.u8(),
+                    codecopy_pc,
+                    initcode,
+                    constructor,
+                    runtime,
+                })
+            })
+            .collect::<Vec<_>>()
+    }).collect()
+}
+
+fn analyze(code: Vec<u8>) -> Program {
+    let mut program = Program::parse_bytecode(code, None);
+    program.gen_symbolic_edges();
+    program
+}
@@ -0,0 +1,118 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+use primitive_types::U256;
+
+/// Bounds on how much work the symbolic pass is allowed to do on a single program.
+///
+/// Every limit is checked as states are taken off the work queue, so a pass
+/// that hits one still leaves `Program::edges` holding everything discovered
+/// up to that point.
+#[derive(Debug, Clone)]
+pub struct AnalysisConfig {
+    /// Maximum number of (block, stack) states executed.
+    pub max_states: usize,
+    /// Maximum number of blocks along a single explored path.
+    pub max_depth: usize,
+    /// Maximum number of times a path may follow the same edge.
+    pub max_loop_unroll: usize,
+    /// Wall-clock budget for the whole pass.
+    pub timeout: Option<Duration>,
+    /// How finely states reaching the same block are kept apart.
+    pub context_sensitivity: ContextSensitivity,
+}
+
+/// Precision of the symbolic pass around internal function calls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContextSensitivity {
+    /// Never merge: every distinct stack reaching a block is explored on its own.
+    PathSensitive,
+    /// Merge stacks reaching a block under the same last `k` internal call sites
+    /// (k-CFA). `CallString(0)` merges everything reaching a block.
+    CallString(usize),
+}
+
+impl Default for AnalysisConfig {
+    fn default() -> Self {
+        AnalysisConfig {
+            max_states: 10_000,
+            max_depth: 1_024,
+            max_loop_unroll: 2,
+            timeout: None,
+            context_sensitivity: ContextSensitivity::CallString(2),
+        }
+    }
+}
+
+impl AnalysisConfig {
+    pub fn max_states(mut self, max_states: usize) -> Self {
+        self.max_states = max_states;
+        self
+    }
+
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    pub fn max_loop_unroll(mut self, max_loop_unroll: usize) -> Self {
+        self.max_loop_unroll = max_loop_unroll;
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn context_sensitivity(mut self, context_sensitivity: ContextSensitivity) -> Self {
+        self.context_sensitivity = context_sensitivity;
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum AnalysisLimit {
+    MaxStates,
+    MaxDepth,
+    LoopUnroll,
+    Timeout,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnresolvedReason {
+    /// Every path reaching the jump was cut by this limit before the jump executed.
+    Limit(AnalysisLimit),
+    /// The jump was executed with a target that was not a known constant,
+    /// on at least one path.
+    UnknownTarget,
+    /// The jump was never reached and exploration stopped early, so it may be live.
+    Unexplored,
+}
+
+/// Outcome of a bounded symbolic pass.
+#[derive(Debug, Clone, Default)]
+pub struct AnalysisReport {
+    pub states_explored: usize,
+    pub limits_hit: BTreeSet<AnalysisLimit>,
+    /// Jump pcs for which no target was resolved, keyed by pc.
+    pub unresolved_jumps: BTreeMap<U256, UnresolvedReason>,
+    /// Jump blocks executed at least once.
+    pub jumps_reached: usize,
+    /// Jump blocks whose target was known on every path reaching them.
+    pub jumps_resolved: usize,
+}
+
+impl AnalysisReport {
+    pub fn is_complete(&self) -> bool {
+        self.limits_hit.is_empty()
+    }
+
+    /// Share of the jumps reached whose targets were always resolved; 1.0 if none were reached.
+    pub fn resolution_rate(&self) -> f64 {
+        if self.jumps_reached == 0 {
+            1.0
+        } else {
+            self.jumps_resolved as f64 / self.jumps_reached as f64
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use std::collections::BTreeSet;
+use primitive_types::U256;
+use crate::op::Operation;
+use crate::stack::AbstractValue;
+
+/// Most constants `ReturnTargets` keeps for one slot before giving up.
+pub const MAX_RETURN_TARGETS: usize = 16;
+
+/// An internal call: the jump that entered the callee and the address it
+/// left on the stack to come back to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CallSite {
+    pub pc: U256,
+    pub return_address: U256,
+}
+
+/// The innermost internal calls on the current path, most recent last,
+/// truncated to the configured call-string length.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct CallContext {
+    sites: Vec<CallSite>,
+}
+
+impl CallContext {
+    pub fn returns_to(&self) -> Option<U256> {
+        self.sites.last().map(|site| site.return_address)
+    }
+
+    /// Whether any call on the current path returns to `address`.
+    pub fn returns_through(&self, address: U256) -> bool {
+        self.sites.iter().any(|site| site.return_address == address)
+    }
+
+    pub fn enter(&self, site: CallSite, k: usize) -> CallContext {
+        let mut sites = self.sites.clone();
+        sites.push(site);
+        if sites.len() > k {
+            sites.drain(..sites.len() - k);
+        }
+        CallContext { sites }
+    }
+
+    pub fn leave(&self) -> CallContext {
+        let mut sites = self.sites.clone();
+        sites.pop();
+        CallContext { sites }
+    }
+}
+
+/// The pushed constants a stack slot may hold, kept beside the symbolic stack
+/// so that a return jump reached under a merged call context still knows
+/// every return address that was joined into it; `None` once the slot holds
+/// a computed value or too many constants.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReturnTargets(pub Option<BTreeSet<U256>>);
+
+impl AbstractValue for ReturnTargets {
+    fn unknown() -> Self {
+        ReturnTargets(None)
+    }
+
+    fn constant(bytes: &[u8]) -> Self {
+        ReturnTargets(Some(BTreeSet::from([U256::from_big_endian(bytes)])))
+    }
+
+    fn join(&self, other: &Self) -> Self {
+        ReturnTargets(self.0.as_ref().zip(other.0.as_ref())
+            .map(|(a, b)| a.union(b).copied().collect::<BTreeSet<_>>())
+            .filter(|targets| targets.len() <= MAX_RETURN_TARGETS))
+    }
+
+    fn transfer(_op: &Operation, _args: &[Self]) -> Self {
+        ReturnTargets(None)
+    }
+
+    fn as_u256(&self) -> Option<U256> {
+        match &self.0 {
+            Some(targets) if targets.len() == 1 => targets.iter().next().copied(),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use std::collections::{BTreeSet, VecDeque};
+use std::ops::Range;
+use petgraph::stable_graph::NodeIndex;
+use primitive_types::U256;
+use revm::opcode::*;
+use crate::stack::SymbolicStack;
+use crate::{BlockKind, Program};
+
+/// What a byte of `Program::code` turned out to be.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ByteClass {
+    /// An opcode in a block reachable from the entry.
+    Code,
+    /// An opcode in a block no edge leads to.
+    UnreachableCode,
+    /// A PUSH immediate.
+    PushData,
+    /// The CBOR metadata trailer solc and Vyper append.
+    Metadata,
+    /// Bytes read with CODECOPY: constants, jump tables, child initcode.
+    Data,
+}
+
+/// Byte counts per `ByteClass`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CoverageSummary {
+    pub code: usize,
+    pub unreachable_code: usize,
+    pub push_data: usize,
+    pub metadata: usize,
+    pub data: usize,
+}
+
+impl CoverageSummary {
+    /// Share of opcodes that are reachable; 1.0 for a program without any.
+    pub fn reachable_ratio(&self) -> f64 {
+        let opcodes = self.code + self.unreachable_code;
+        if opcodes == 0 {
+            1.0
+        } else {
+            self.code as f64 / opcodes as f64
+        }
+    }
+}
+
+/// Classification of every byte of a program's code against its current CFG.
+#[derive(Debug, Clone, Default)]
+pub struct Coverage {
+    pub classes: Vec<ByteClass>,
+    pub summary: CoverageSummary,
+}
+
+impl Coverage {
+    pub fn new(program: &Program) -> Self {
+        let mut classes = vec![ByteClass::UnreachableCode; program.code.len()];
+        let reachable = reachable_blocks(program);
+        program.block_nodes().for_each(|node| {
+            let live = reachable.contains(&node);
+            program.cfg[node].ops.iter().for_each(|op| {
+                let pc = match op.pc {
+                    Some(pc) => pc.as_usize(),
+                    None => return,
+                };
+                classes[pc] = if live { ByteClass::Code } else { ByteClass::UnreachableCode };
+                let end = (pc + 1 + op.arg_size as usize).min(classes.len());
+                (pc + 1..end).for_each(|pc| classes[pc] = ByteClass::PushData);
+            });
+        });
+        // Copying out reachable code, as initcode does with the runtime, does
+        // not make it data.
+        data_ranges(program, &reachable).into_iter().flatten().for_each(|pc| {
+            if classes[pc] != ByteClass::Code {
+                classes[pc] = ByteClass::Data;
+            }
+        });
+        program.metadata.clone().into_iter().flatten().for_each(|pc| classes[pc] = ByteClass::Metadata);
+
+        let mut summary = CoverageSummary::default();
+        classes.iter().for_each(|class| match class {
+            ByteClass::Code => summary.code += 1,
+            ByteClass::UnreachableCode => summary.unreachable_code += 1,
+            ByteClass::PushData => summary.push_data += 1,
+            ByteClass::Metadata => summary.metadata += 1,
+            ByteClass::Data => summary.data += 1,
+        });
+        Coverage { classes, summary }
+    }
+
+    pub fn class_at(&self, pc: usize) -> Option<ByteClass> {
+        self.classes.get(pc).copied()
+    }
+
+    /// Maximal runs of bytes sharing a class, in pc order.
+    pub fn ranges(&self) -> Vec<(Range<usize>, ByteClass)> {
+        let mut ranges: Vec<(Range<usize>, ByteClass)> = vec![];
+        self.classes.iter().enumerate().for_each(|(pc, class)| match ranges.last_mut() {
+            Some((range, last)) if last == class => range.end = pc + 1,
+            _ => ranges.push((pc..pc + 1, *class)),
+        });
+        ranges
+    }
+}
+
+/// The CBOR metadata trailer: a map whose length is given by the two bytes
+/// after it. Zero padding after the length, as some block explorers serve
+/// the code with, is included. The map must hold only keys solc and Vyper
+/// write and end right at the length, so trailing code that merely looks
+/// like a length is not mistaken for metadata.
+pub fn metadata_range(code: &[u8]) -> Option<Range<usize>> {
+    let padded = code.len() - code.iter().rev().take_while(|byte| **byte == 0).count();
+    [code.len(), padded].iter().find_map(|end| {
+        let len_at = end.checked_sub(2)?;
+        let len = u16::from_be_bytes([code[len_at], code[len_at + 1]]) as usize;
+        let start = len_at.checked_sub(len)?;
+        (len > 0 && is_metadata_map(&code[start..len_at])).then(|| start..code.len())
+    })
+}
+
+const METADATA_KEYS: [&[u8]; 6] = [b"ipfs", b"bzzr0", b"bzzr1", b"solc", b"experimental", b"vyper"];
+
+/// Whether `map` is exactly one CBOR map of one to five entries, keyed by
+/// `METADATA_KEYS` and holding byte strings, text strings, booleans or, for
+/// Vyper's version, an array of small integers.
+fn is_metadata_map(map: &[u8]) -> bool {
+    // A byte or text string's contents, and the offset past them.
+    fn string(map: &[u8], at: usize, major: u8) -> Option<(&[u8], usize)> {
+        let head = *map.get(at)?;
+        let (len, from) = match head.checked_sub(major << 5)? {
+            short @ 0..=23 => (short as usize, at + 1),
+            24 => (*map.get(at + 1)? as usize, at + 2),
+            _ => return None,
+        };
+        Some((map.get(from..from + len)?, from + len))
+    }
+
+    let entries = match map.first() {
+        Some(head @ 0xa1..=0xa5) => head - 0xa0,
+        _ => return false,
+    };
+    let mut at = 1;
+    for _ in 0..entries {
+        at = match string(map, at, 3) {
+            Some((key, next)) if METADATA_KEYS.contains(&key) => next,
+            _ => return false,
+        };
+        at = match map.get(at) {
+            Some(0xf4 | 0xf5) => at + 1,
+            Some(head @ 0x81..=0x87) => {
+                let items = map.get(at + 1..at + 1 + (head - 0x80) as usize).unwrap_or_default();
+                if items.len() != (head - 0x80) as usize || items.iter().any(|item| *item > 0x17) {
+                    return false;
+                }
+                at + 1 + items.len()
+            },
+            Some(head) => match string(map, at, head >> 5).filter(|_| matches!(head >> 5, 2 | 3)) {
+                Some((_, next)) => next,
+                None => return false,
+            },
+            None => return false,
+        };
+    }
+    at == map.len()
+}
+
+fn reachable_blocks(program: &Program) -> BTreeSet<NodeIndex> {
+    let mut reachable = BTreeSet::new();
+    let mut queue = VecDeque::from([program.synthetic.entry]);
+    while let Some(node) = queue.pop_front() {
+        if program.cfg[node].kind == BlockKind::Code && !reachable.insert(node) {
+            continue;
+        }
+        queue.extend(program.cfg.neighbors(node).filter(|next| !reachable.contains(next)));
+    }
+    reachable
+}
+
+/// Ranges copied by CODECOPYs with a constant offset and size in reachable
+/// blocks, plus any Vyper jump tables.
+fn data_ranges(program: &Program, reachable: &BTreeSet<NodeIndex>) -> Vec<Range<usize>> {
+    let copied = reachable.iter()
+        .flat_map(|node| constant_codecopies(program, *node))
+        .map(|(_, range)| range);
+    let tables = program.jump_tables.iter().map(|table| table.data.clone());
+    copied.chain(tables).collect()
+}
+
+/// The pc and source range of every CODECOPY in `node` whose offset and size
+/// are constants within the block.
+pub(crate) fn constant_codecopies(program: &Program, node: NodeIndex) -> Vec<(U256, Range<usize>)> {
+    let block = &program.cfg[node];
+    let mut stack = SymbolicStack::new();
+    let mut copies = vec![];
+    block.ops.iter().for_each(|op| {
+        if op.code.u8() == CODECOPY {
+            // CODECOPY(destOffset, offset, size)
+            if let (Some(pc), Some(offset), Some(size)) = (op.pc, stack.peek_at(1).as_u256(), stack.peek_at(2).as_u256()) {
+                let end = offset.saturating_add(size);
+                if end <= program.code.len().into() && !size.is_zero() {
+                    copies.push((pc, offset.as_usize()..end.as_usize()));
+                }
+            }
+        }
+        stack.execute(op, &program.code);
+    });
+    copies
+}
@@ -0,0 +1,219 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::marker::PhantomData;
+use petgraph::stable_graph::NodeIndex;
+use petgraph::Direction;
+use primitive_types::U256;
+use crate::op::Operation;
+use crate::stack::{AbstractValue, SymbolicStack};
+use crate::Program;
+
+/// Which way facts flow along `Program::cfg` edges.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FlowDirection {
+    /// From the entry node towards the sinks, e.g. reaching definitions.
+    Forward,
+    /// From the sinks towards the entry node, e.g. liveness.
+    Backward,
+}
+
+/// A monotone data-flow problem over a program's CFG. Only `bottom`, `join`
+/// and a transfer function are required; `solve` does the iteration.
+pub trait DataFlowAnalysis {
+    /// An element of the analysis lattice.
+    type Fact: Clone + PartialEq;
+
+    const DIRECTION: FlowDirection = FlowDirection::Forward;
+
+    /// The least element, which every block starts out with.
+    fn bottom(&self) -> Self::Fact;
+
+    /// The fact entering the program: at the synthetic entry node going
+    /// forward, at every node without successors going backward.
+    fn boundary(&self) -> Self::Fact {
+        self.bottom()
+    }
+
+    /// Merges `other` into `fact` where paths meet.
+    fn join(&self, fact: &mut Self::Fact, other: &Self::Fact);
+
+    /// Called with a block's previous and newly computed output whenever it
+    /// is recomputed, so lattices of unbounded height can jump ahead.
+    fn widen(&self, _previous: &Self::Fact, next: Self::Fact) -> Self::Fact {
+        next
+    }
+
+    /// Effect of a single operation on `fact`.
+    fn transfer(&self, _program: &Program, _op: &Operation, _fact: &mut Self::Fact) {}
+
+    /// Effect of a whole block; applies `transfer` to its operations in the
+    /// analysis direction unless overridden. Synthetic nodes have none.
+    fn transfer_block(&self, program: &Program, node: NodeIndex, fact: &mut Self::Fact) {
+        let ops = &program.cfg[node].ops;
+        match Self::DIRECTION {
+            FlowDirection::Forward => ops.iter().for_each(|op| self.transfer(program, op, fact)),
+            FlowDirection::Backward => ops.iter().rev().for_each(|op| self.transfer(program, op, fact)),
+        }
+    }
+}
+
+/// Fixpoint of a `DataFlowAnalysis`, at both ends of every node.
+#[derive(Debug, Clone)]
+pub struct DataFlowResults<F> {
+    /// Fact at the start of each node, in pc order whatever the direction.
+    pub entry: HashMap<NodeIndex, F>,
+    /// Fact at the end of each node.
+    pub exit: HashMap<NodeIndex, F>,
+}
+
+impl<F: Clone + PartialEq> DataFlowResults<F> {
+    /// The fact each operation of `node` sees, paired with its pc: the one
+    /// before it runs going forward, the one after it going backward.
+    pub fn at_ops<A>(&self, analysis: &A, program: &Program, node: NodeIndex) -> Vec<(U256, F)>
+    where
+        A: DataFlowAnalysis<Fact = F>,
+    {
+        let ops = &program.cfg[node].ops;
+        let mut facts = vec![];
+        let mut step = |op: &Operation, fact: &mut F| {
+            facts.push((op.pc.unwrap_or_default(), fact.clone()));
+            analysis.transfer(program, op, fact);
+        };
+        match A::DIRECTION {
+            FlowDirection::Forward => {
+                let mut fact = self.entry[&node].clone();
+                ops.iter().for_each(|op| step(op, &mut fact));
+            },
+            FlowDirection::Backward => {
+                let mut fact = self.exit[&node].clone();
+                ops.iter().rev().for_each(|op| step(op, &mut fact));
+                facts.reverse();
+            },
+        }
+        facts
+    }
+}
+
+/// Runs `analysis` to a fixpoint over every node of `program.cfg` with a
+/// worklist, revisiting a node's successors (in the analysis direction)
+/// only when its output changes.
+pub fn solve<A: DataFlowAnalysis>(analysis: &A, program: &Program) -> DataFlowResults<A::Fact> {
+    let cfg = &program.cfg;
+    let (upstream, downstream) = match A::DIRECTION {
+        FlowDirection::Forward => (Direction::Incoming, Direction::Outgoing),
+        FlowDirection::Backward => (Direction::Outgoing, Direction::Incoming),
+    };
+    let boundary = cfg.node_indices().filter(|node| match A::DIRECTION {
+        FlowDirection::Forward => *node == program.synthetic.entry,
+        FlowDirection::Backward => cfg.neighbors(*node).next().is_none(),
+    }).collect::<HashSet<_>>();
+
+    let mut inputs: HashMap<NodeIndex, A::Fact> = HashMap::new();
+    let mut outputs: HashMap<NodeIndex, A::Fact> = HashMap::new();
+    let mut queue = match A::DIRECTION {
+        FlowDirection::Forward => cfg.node_indices().collect::<VecDeque<_>>(),
+        FlowDirection::Backward => cfg.node_indices().rev().collect::<VecDeque<_>>(),
+    };
+    let mut queued = queue.iter().copied().collect::<HashSet<_>>();
+    while let Some(node) = queue.pop_front() {
+        queued.remove(&node);
+        let mut input = if boundary.contains(&node) { analysis.boundary() } else { analysis.bottom() };
+        cfg.neighbors_directed(node, upstream).for_each(|prev| {
+            if let Some(fact) = outputs.get(&prev) {
+                analysis.join(&mut input, fact);
+            }
+        });
+        let mut output = input.clone();
+        analysis.transfer_block(program, node, &mut output);
+        inputs.insert(node, input);
+        let output = match outputs.get(&node) {
+            Some(previous) => {
+                let output = analysis.widen(previous, output);
+                if *previous == output {
+                    continue;
+                }
+                output
+            },
+            None => output,
+        };
+        outputs.insert(node, output);
+        cfg.neighbors_directed(node, downstream).for_each(|next| {
+            if queued.insert(next) {
+                queue.push_back(next);
+            }
+        });
+    }
+
+    match A::DIRECTION {
+        FlowDirection::Forward => DataFlowResults { entry: inputs, exit: outputs },
+        FlowDirection::Backward => DataFlowResults { entry: outputs, exit: inputs },
+    }
+}
+
+/// What `StackAnalysis` carries along a path: an abstract stack, plus
+/// whatever an analysis tracks beside it.
+pub trait StackState: Clone + PartialEq + Default {
+    /// Effect of `op`, reading PUSH immediates from `code`.
+    fn execute(&mut self, op: &Operation, code: &[u8]);
+
+    /// Merges `other` into `self` where paths meet.
+    fn join(&mut self, other: &Self);
+
+    /// See `DataFlowAnalysis::widen`.
+    fn widen(&self, next: Self) -> Self {
+        next
+    }
+}
+
+impl<V: AbstractValue> StackState for SymbolicStack<V> {
+    fn execute(&mut self, op: &Operation, code: &[u8]) {
+        SymbolicStack::execute(self, op, code);
+    }
+
+    fn join(&mut self, other: &Self) {
+        *self = SymbolicStack::from(self.capture().join(&other.capture()));
+    }
+}
+
+/// Forward propagation of a `StackState` from the entry; a block's fact is
+/// `None` until some path reaches it.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StackAnalysis<S>(PhantomData<S>);
+
+impl<S> StackAnalysis<S> {
+    pub const fn new() -> Self {
+        StackAnalysis(PhantomData)
+    }
+}
+
+impl<S: StackState> DataFlowAnalysis for StackAnalysis<S> {
+    type Fact = Option<S>;
+
+    fn bottom(&self) -> Self::Fact {
+        None
+    }
+
+    fn boundary(&self) -> Self::Fact {
+        Some(S::default())
+    }
+
+    fn join(&self, fact: &mut Self::Fact, other: &Self::Fact) {
+        match (fact.as_mut(), other) {
+            (_, None) => {},
+            (None, Some(other)) => *fact = Some(other.clone()),
+            (Some(state), Some(other)) => state.join(other),
+        }
+    }
+
+    fn widen(&self, previous: &Self::Fact, next: Self::Fact) -> Self::Fact {
+        match (previous, next) {
+            (Some(previous), Some(next)) => Some(previous.widen(next)),
+            (_, next) => next,
+        }
+    }
+
+    fn transfer(&self, program: &Program, op: &Operation, fact: &mut Self::Fact) {
+        if let Some(state) = fact {
+            state.execute(op, &program.code);
+        }
+    }
+}
@@ -0,0 +1,246 @@
+use std::collections::BTreeSet;
+use primitive_types::U256;
+use revm::opcode::*;
+use crate::dataflow::{StackAnalysis, StackState};
+use crate::op::Operation;
+use crate::stack::{AbstractValue, SymbolicStack, SymbolicStackValue};
+use crate::taint::TaintedMemory;
+use crate::Program;
+
+/// A value the transaction's sender or the block producer picks or can
+/// predict.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum EnvironmentSource {
+    Origin,
+    Timestamp,
+    Number,
+    Blockhash,
+    /// PREVRANDAO, DIFFICULTY before the merge.
+    Prevrandao,
+    Coinbase,
+}
+
+impl EnvironmentSource {
+    fn of(opcode: u8) -> Option<Self> {
+        match opcode {
+            ORIGIN => Some(EnvironmentSource::Origin),
+            TIMESTAMP => Some(EnvironmentSource::Timestamp),
+            NUMBER => Some(EnvironmentSource::Number),
+            BLOCKHASH => Some(EnvironmentSource::Blockhash),
+            DIFFICULTY => Some(EnvironmentSource::Prevrandao),
+            COINBASE => Some(EnvironmentSource::Coinbase),
+            _ => None,
+        }
+    }
+}
+
+/// What a value derived from an `EnvironmentSource` was used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum DependenceUse {
+    /// ORIGIN compared for equality with anything but CALLER, as in
+    /// `tx.origin == owner`; `tx.origin == msg.sender` only rules out
+    /// contract callers.
+    Authentication,
+    /// A block value reduced modulo something, or hashed and then reduced or
+    /// branched on, or a block hash or PREVRANDAO at all. A hash used as a
+    /// storage key is not.
+    Randomness,
+    /// Anything else, e.g. a deadline check or a recorded block number.
+    Data,
+}
+
+/// The operand of an operation an environment value should not decide.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum DependenceSink {
+    /// Condition of a JUMPI.
+    Branch,
+    /// Value of a CALL or CALLCODE.
+    CallValue,
+    /// Slot or value of an SSTORE.
+    StorageWrite,
+}
+
+/// An environment value read at `pc`, and how it has been used so far.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Dependency {
+    pub pc: U256,
+    pub source: EnvironmentSource,
+    pub usage: DependenceUse,
+    /// Whether the value went through SHA3 on the way.
+    pub hashed: bool,
+}
+
+impl Dependency {
+    /// This dependency once its value is used as `usage`, if that use applies
+    /// to its source.
+    fn used_as(self, usage: DependenceUse) -> Self {
+        let applies = match usage {
+            DependenceUse::Authentication => self.source == EnvironmentSource::Origin,
+            DependenceUse::Randomness => self.source != EnvironmentSource::Origin,
+            DependenceUse::Data => false,
+        };
+        if applies { Dependency { usage, ..self } } else { self }
+    }
+}
+
+/// An environment value reaching a sink.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct EnvironmentDependence {
+    /// External function holding the sink; `None` outside any recovered one.
+    pub selector: Option<[u8; 4]>,
+    pub source: EnvironmentSource,
+    pub source_pc: U256,
+    pub sink: DependenceSink,
+    pub sink_pc: U256,
+    pub usage: DependenceUse,
+}
+
+/// A stack value in the default domain, plus the environment values it was
+/// computed from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnvironmentValue {
+    pub value: SymbolicStackValue,
+    pub dependencies: BTreeSet<Dependency>,
+    /// Derived from CALLER.
+    pub caller: bool,
+}
+
+impl EnvironmentValue {
+    fn with_value(value: SymbolicStackValue) -> Self {
+        EnvironmentValue { value, dependencies: BTreeSet::new(), caller: false }
+    }
+}
+
+impl AbstractValue for EnvironmentValue {
+    fn unknown() -> Self {
+        EnvironmentValue::with_value(SymbolicStackValue::Unknown)
+    }
+
+    fn uninitialized() -> Self {
+        EnvironmentValue::with_value(SymbolicStackValue::Uninitialized)
+    }
+
+    fn constant(bytes: &[u8]) -> Self {
+        EnvironmentValue::with_value(SymbolicStackValue::constant(bytes))
+    }
+
+    fn join(&self, other: &Self) -> Self {
+        EnvironmentValue {
+            value: self.value.join(&other.value),
+            dependencies: self.dependencies.union(&other.dependencies).copied().collect(),
+            caller: self.caller || other.caller,
+        }
+    }
+
+    fn transfer(op: &Operation, args: &[Self]) -> Self {
+        let values = args.iter().map(|arg| arg.value).collect::<Vec<_>>();
+        let caller = op.code.u8() == CALLER || args.iter().any(|arg| arg.caller);
+        let usage = match op.code.u8() {
+            EQ if !caller => Some(DependenceUse::Authentication),
+            MOD => Some(DependenceUse::Randomness),
+            _ => None,
+        };
+        let mut dependencies = args.iter()
+            .flat_map(|arg| arg.dependencies.iter())
+            .map(|dependency| usage.map_or(*dependency, |usage| dependency.used_as(usage)))
+            .collect::<BTreeSet<_>>();
+        if let (Some(source), Some(pc)) = (EnvironmentSource::of(op.code.u8()), op.pc) {
+            let usage = match source {
+                EnvironmentSource::Blockhash | EnvironmentSource::Prevrandao => DependenceUse::Randomness,
+                _ => DependenceUse::Data,
+            };
+            dependencies.insert(Dependency { pc, source, usage, hashed: false });
+        }
+        EnvironmentValue { value: SymbolicStackValue::transfer(op, &values), dependencies, caller }
+    }
+
+    fn as_u256(&self) -> Option<U256> {
+        self.value.as_u256()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DependenceState {
+    pub stack: SymbolicStack<EnvironmentValue>,
+    /// Environment values stored in memory, so hashing them is seen.
+    pub memory: TaintedMemory<Dependency>,
+}
+
+impl StackState for DependenceState {
+    fn execute(&mut self, op: &Operation, code: &[u8]) {
+        let args = (0..3).map(|depth| self.stack.peek_at(depth)).collect::<Vec<_>>();
+        let mut read = None;
+        match op.code.u8() {
+            MLOAD => read = Some((args[0].clone(), Some(U256::from(32)))),
+            SHA3 => read = Some((args[0].clone(), args[1].as_u256())),
+            MSTORE => self.memory.write(&args[0], Some(U256::from(32)), &args[1].dependencies),
+            MSTORE8 => self.memory.write(&args[0], Some(U256::one()), &args[1].dependencies),
+            CALLDATACOPY | CODECOPY | RETURNDATACOPY => self.memory.write(&args[0], args[2].as_u256(), &BTreeSet::new()),
+            _ => {},
+        }
+        self.stack.execute(op, code);
+        if let Some((offset, size)) = read {
+            let mut top = self.stack.pop();
+            let hashed = op.code.u8() == SHA3;
+            top.dependencies.extend(self.memory.read(&offset, size).into_iter()
+                .map(|dependency| Dependency { hashed: dependency.hashed || hashed, ..dependency }));
+            self.stack.push(top);
+        }
+    }
+
+    fn join(&mut self, other: &Self) {
+        self.stack.join(&other.stack);
+        self.memory.join(&other.memory);
+    }
+}
+
+/// Forward propagation of environment values.
+pub type EnvironmentDependencies = StackAnalysis<DependenceState>;
+
+/// Operands of `opcode` environment values should not decide, by depth.
+fn sinks(opcode: u8) -> &'static [(usize, DependenceSink)] {
+    match opcode {
+        JUMPI => &[(1, DependenceSink::Branch)],
+        CALL | CALLCODE => &[(2, DependenceSink::CallValue)],
+        SSTORE => &[(0, DependenceSink::StorageWrite), (1, DependenceSink::StorageWrite)],
+        _ => &[],
+    }
+}
+
+/// Every ORIGIN, TIMESTAMP, NUMBER, BLOCKHASH, PREVRANDAO and COINBASE read
+/// whose value reaches a branch condition, a call's value or a storage
+/// write, once per use and external function holding the sink.
+pub fn environment_dependence(program: &Program) -> Vec<EnvironmentDependence> {
+    let results = program.solve(&EnvironmentDependencies::new());
+    let dispatcher = program.dispatcher();
+    let mut findings = BTreeSet::new();
+    program.block_nodes().for_each(|node| {
+        let ops = &program.cfg[node].ops;
+        let selectors = dispatcher.selectors_containing(&[node]);
+        results.at_ops(&EnvironmentDependencies::new(), program, node).into_iter().zip(ops).for_each(|((sink_pc, state), op)| {
+            let state = match state {
+                Some(state) => state,
+                None => return,
+            };
+            sinks(op.code.u8()).iter().for_each(|(depth, sink)| {
+                state.stack.peek_at(*depth).dependencies.iter().for_each(|dependency| {
+                    let dependency = match sink {
+                        DependenceSink::Branch if dependency.hashed => dependency.used_as(DependenceUse::Randomness),
+                        _ => *dependency,
+                    };
+                    selectors.iter().for_each(|selector| {
+                        findings.insert(EnvironmentDependence {
+                            selector: *selector,
+                            source: dependency.source,
+                            source_pc: dependency.pc,
+                            sink: *sink,
+                            sink_pc,
+                            usage: dependency.usage,
+                        });
+                    });
+                });
+            });
+        });
+    });
+    findings.into_iter().collect()
+}
@@ -0,0 +1,464 @@
+use std::ops::Range;
+use primitive_types::U256;
+use revm::opcode::*;
+use serde_json::{json, Value};
+use crate::access::PrivilegedOp;
+use crate::arbitrary::ControlledSiteKind;
+use crate::dependence::DependenceUse;
+use crate::taint::{TaintSinkKind, TaintSourceKind};
+use crate::unchecked::UncheckedKind;
+use crate::Program;
+
+/// SARIF version `DetectorRegistry::sarif` writes.
+pub const SARIF_VERSION: &str = "2.1.0";
+pub const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Severity {
+    Informational,
+    Low,
+    Medium,
+    High,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Informational => "informational",
+            Severity::Low => "low",
+            Severity::Medium => "medium",
+            Severity::High => "high",
+        }
+    }
+
+    /// The SARIF `level` of a result.
+    fn level(&self) -> &'static str {
+        match self {
+            Severity::High => "error",
+            Severity::Medium => "warning",
+            Severity::Low | Severity::Informational => "note",
+        }
+    }
+}
+
+/// How likely a finding is to be a real issue rather than an artifact of the
+/// analysis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Confidence {
+    Low,
+    Medium,
+    High,
+}
+
+impl Confidence {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Confidence::Low => "low",
+            Confidence::Medium => "medium",
+            Confidence::High => "high",
+        }
+    }
+}
+
+/// An issue a detector reported.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Finding {
+    /// Name of the detector that reported it.
+    pub id: &'static str,
+    pub severity: Severity,
+    pub confidence: Confidence,
+    /// Bytes of the op the issue is reported at: the sink of a flow, or
+    /// the only op involved.
+    pub pcs: Range<usize>,
+    /// Other ops the issue involves, such as where a flow starts.
+    pub related: Vec<RelatedLocation>,
+    pub message: String,
+}
+
+/// An op a finding involves besides the one it is reported at.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RelatedLocation {
+    pub pcs: Range<usize>,
+    /// What the op has to do with the finding, e.g. "source".
+    pub message: String,
+}
+
+impl RelatedLocation {
+    fn at(pc: U256, message: &str) -> Self {
+        RelatedLocation { pcs: op(pc), message: message.to_string() }
+    }
+}
+
+impl Finding {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "id": self.id,
+            "severity": self.severity.as_str(),
+            "confidence": self.confidence.as_str(),
+            "pcs": { "start": self.pcs.start, "end": self.pcs.end },
+            "related": self.related.iter().map(|related| json!({
+                "pcs": { "start": related.pcs.start, "end": related.pcs.end },
+                "message": related.message,
+            })).collect::<Vec<_>>(),
+            "message": self.message,
+        })
+    }
+}
+
+/// `findings` as a JSON array.
+pub fn findings_json(findings: &[Finding]) -> Value {
+    Value::Array(findings.iter().map(Finding::to_json).collect())
+}
+
+/// A check run over an analyzed program.
+pub trait Detector {
+    /// Unique kebab-case name, used as the id of its findings and to enable
+    /// or disable it.
+    fn name(&self) -> &'static str;
+
+    /// One sentence on what it looks for.
+    fn description(&self) -> &'static str;
+
+    /// Findings in `program`, whose edges should already be generated.
+    fn detect(&self, program: &Program) -> Vec<Finding>;
+}
+
+/// Detectors by name, each enabled or not.
+pub struct DetectorRegistry {
+    detectors: Vec<(Box<dyn Detector>, bool)>,
+}
+
+impl Default for DetectorRegistry {
+    /// Every built-in detector, enabled.
+    fn default() -> Self {
+        let mut registry = DetectorRegistry::new();
+        registry.register(Box::new(TaintFlowDetector));
+        registry.register(Box::new(ReentrancyDetector));
+        registry.register(Box::new(UnprotectedOperationDetector));
+        registry.register(Box::new(CalldataControlDetector));
+        registry.register(Box::new(UncheckedCallDetector));
+        registry.register(Box::new(EnvironmentDependenceDetector));
+        registry
+    }
+}
+
+impl DetectorRegistry {
+    /// A registry without any detectors.
+    pub fn new() -> Self {
+        DetectorRegistry { detectors: vec![] }
+    }
+
+    /// Adds `detector`, enabled, replacing any registered under its name.
+    pub fn register(&mut self, detector: Box<dyn Detector>) {
+        self.detectors.retain(|(registered, _)| registered.name() != detector.name());
+        self.detectors.push((detector, true));
+    }
+
+    /// Turns the named detector on; false if there is none by that name.
+    pub fn enable(&mut self, name: &str) -> bool {
+        self.set_enabled(name, true)
+    }
+
+    /// Turns the named detector off; false if there is none by that name.
+    pub fn disable(&mut self, name: &str) -> bool {
+        self.set_enabled(name, false)
+    }
+
+    fn set_enabled(&mut self, name: &str, enabled: bool) -> bool {
+        self.detectors.iter_mut()
+            .find(|(detector, _)| detector.name() == name)
+            .map(|(_, state)| *state = enabled)
+            .is_some()
+    }
+
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.detectors.iter().any(|(detector, enabled)| *enabled && detector.name() == name)
+    }
+
+    /// Names of every registered detector, in registration order.
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.detectors.iter().map(|(detector, _)| detector.name())
+    }
+
+    fn enabled(&self) -> impl Iterator<Item = &dyn Detector> + '_ {
+        self.detectors.iter().filter(|(_, enabled)| *enabled).map(|(detector, _)| detector.as_ref())
+    }
+
+    /// Findings of every enabled detector, ordered by pc.
+    pub fn run(&self, program: &Program) -> Vec<Finding> {
+        let mut findings = self.enabled().flat_map(|detector| detector.detect(program)).collect::<Vec<_>>();
+        findings.sort_by(|a, b| (a.pcs.start, a.pcs.end, a.id, &a.message).cmp(&(b.pcs.start, b.pcs.end, b.id, &b.message)));
+        findings.dedup();
+        findings
+    }
+
+    /// `findings` as a SARIF 2.1 log with one run, whose rules are the enabled
+    /// detectors. Locations are byte regions of `artifact`, the URI of the
+    /// analyzed bytecode: each result's `pcs` as its location and its
+    /// `related` ops as `relatedLocations`.
+    pub fn sarif(&self, findings: &[Finding], artifact: &str) -> Value {
+        let region = |pcs: &Range<usize>| json!({
+            "artifactLocation": { "uri": artifact },
+            "region": { "byteOffset": pcs.start, "byteLength": pcs.end - pcs.start },
+        });
+        let rules = self.enabled().map(|detector| json!({
+            "id": detector.name(),
+            "shortDescription": { "text": detector.description() },
+        })).collect::<Vec<_>>();
+        let results = findings.iter().map(|finding| {
+            let mut result = json!({
+                "ruleId": finding.id,
+                "level": finding.severity.level(),
+                "message": { "text": finding.message },
+                "locations": [{ "physicalLocation": region(&finding.pcs) }],
+                "properties": {
+                    "severity": finding.severity.as_str(),
+                    "confidence": finding.confidence.as_str(),
+                },
+            });
+            if !finding.related.is_empty() {
+                result["relatedLocations"] = finding.related.iter().enumerate().map(|(id, related)| json!({
+                    "id": id,
+                    "physicalLocation": region(&related.pcs),
+                    "message": { "text": related.message },
+                })).collect();
+            }
+            if let Some(index) = self.enabled().position(|detector| detector.name() == finding.id) {
+                result["ruleIndex"] = json!(index);
+            }
+            result
+        }).collect::<Vec<_>>();
+        json!({
+            "$schema": SARIF_SCHEMA,
+            "version": SARIF_VERSION,
+            "runs": [{
+                "tool": {
+                    "driver": {
+                        "name": env!("CARGO_PKG_NAME"),
+                        "version": env!("CARGO_PKG_VERSION"),
+                        "rules": rules,
+                    },
+                },
+                "results": results,
+            }],
+        })
+    }
+}
+
+/// The byte of the op at `pc`; no op a detector reports is a PUSH.
+fn op(pc: U256) -> Range<usize> {
+    pc.as_usize()..pc.as_usize() + 1
+}
+
+fn function(selector: Option<[u8; 4]>) -> String {
+    match selector {
+        Some(selector) => format!("function 0x{}", hex::encode(selector)),
+        None => "code outside any external function".to_string(),
+    }
+}
+
+/// `Program::taint_flows`.
+pub struct TaintFlowDetector;
+
+impl Detector for TaintFlowDetector {
+    fn name(&self) -> &'static str {
+        "taint-flow"
+    }
+
+    fn description(&self) -> &'static str {
+        "Attacker-controlled data reaches a jump target, storage slot, call or selfdestruct operand."
+    }
+
+    fn detect(&self, program: &Program) -> Vec<Finding> {
+        program.taint_flows().iter().map(|flow| {
+            let severity = match flow.sink.kind {
+                TaintSinkKind::JumpTarget | TaintSinkKind::StorageSlot | TaintSinkKind::SelfdestructBeneficiary => Severity::High,
+                TaintSinkKind::CallTarget | TaintSinkKind::CallValue => Severity::Medium,
+                TaintSinkKind::Create2Salt => Severity::Low,
+            };
+            // Paying or calling back the sender is what most contracts do.
+            let confidence = match flow.source.kind {
+                TaintSourceKind::Calldata => Confidence::Medium,
+                _ => Confidence::Low,
+            };
+            Finding {
+                id: self.name(),
+                severity,
+                confidence,
+                pcs: op(flow.sink.pc),
+                related: vec![RelatedLocation::at(flow.source.pc, "source")],
+                message: format!(
+                    "{:?} read at {:#x} reaches the {:?} operand at {:#x}",
+                    flow.source.kind, flow.source.pc, flow.sink.kind, flow.sink.pc,
+                ),
+            }
+        }).collect()
+    }
+}
+
+/// `Program::reentrancy`.
+pub struct ReentrancyDetector;
+
+impl Detector for ReentrancyDetector {
+    fn name(&self) -> &'static str {
+        "reentrancy"
+    }
+
+    fn description(&self) -> &'static str {
+        "Storage is written after an external call forwarding more gas than the stipend."
+    }
+
+    fn detect(&self, program: &Program) -> Vec<Finding> {
+        program.reentrancy().iter().map(|reentrancy| Finding {
+            id: self.name(),
+            severity: Severity::High,
+            confidence: Confidence::Medium,
+            pcs: op(reentrancy.write_pc),
+            related: vec![RelatedLocation::at(reentrancy.call_pc, "external call")],
+            message: format!(
+                "SSTORE at {:#x} can run after the call at {:#x} in {}",
+                reentrancy.write_pc, reentrancy.call_pc, function(reentrancy.selector),
+            ),
+        }).collect()
+    }
+}
+
+/// The unguarded entries of `Program::access_control`.
+pub struct UnprotectedOperationDetector;
+
+impl Detector for UnprotectedOperationDetector {
+    fn name(&self) -> &'static str {
+        "unprotected-operation"
+    }
+
+    fn description(&self) -> &'static str {
+        "A selfdestruct, delegatecall or proxy slot write is not dominated by a check of the caller."
+    }
+
+    fn detect(&self, program: &Program) -> Vec<Finding> {
+        program.access_control().iter().filter(|site| !site.is_guarded()).map(|site| {
+            let operation = match site.operation {
+                PrivilegedOp::Selfdestruct => "SELFDESTRUCT".to_string(),
+                PrivilegedOp::Delegatecall => "DELEGATECALL".to_string(),
+                PrivilegedOp::ProxySlotWrite(slot) => format!("SSTORE to proxy slot {:#x}", slot),
+            };
+            Finding {
+                id: self.name(),
+                severity: Severity::High,
+                confidence: Confidence::Medium,
+                pcs: op(site.pc),
+                related: vec![],
+                message: format!("{} at {:#x} in {} does not check the caller", operation, site.pc,
+                                 site.selector.map_or_else(|| "the fallback".to_string(), |selector| function(Some(selector)))),
+            }
+        }).collect()
+    }
+}
+
+/// `Program::controlled_sites`.
+pub struct CalldataControlDetector;
+
+impl Detector for CalldataControlDetector {
+    fn name(&self) -> &'static str {
+        "calldata-control"
+    }
+
+    fn description(&self) -> &'static str {
+        "A jump target or storage slot is computed from calldata."
+    }
+
+    fn detect(&self, program: &Program) -> Vec<Finding> {
+        program.controlled_sites().iter().map(|site| {
+            let kind = match site.kind {
+                ControlledSiteKind::JumpTarget => "jump",
+                ControlledSiteKind::StorageSlot => "storage write",
+            };
+            Finding {
+                id: self.name(),
+                severity: Severity::High,
+                confidence: if site.masked { Confidence::Low } else { Confidence::High },
+                pcs: op(site.pc),
+                related: vec![],
+                message: format!(
+                    "arbitrary {} at {:#x} (calldata {:x?}{})",
+                    kind, site.pc, site.calldata_offsets, if site.masked { ", masked" } else { "" },
+                ),
+            }
+        }).collect()
+    }
+}
+
+/// `Program::unchecked_calls`.
+pub struct UncheckedCallDetector;
+
+impl Detector for UncheckedCallDetector {
+    fn name(&self) -> &'static str {
+        "unchecked-call"
+    }
+
+    fn description(&self) -> &'static str {
+        "The success flag of an external call or send never decides whether to revert."
+    }
+
+    fn detect(&self, program: &Program) -> Vec<Finding> {
+        program.unchecked_calls().iter().map(|call| {
+            let operation = match call.opcode {
+                CALL if call.send => "send",
+                CALLCODE => "CALLCODE",
+                DELEGATECALL => "DELEGATECALL",
+                STATICCALL => "STATICCALL",
+                _ => "CALL",
+            };
+            let (confidence, fate) = match call.kind {
+                UncheckedKind::Popped => (Confidence::High, "is discarded"),
+                UncheckedKind::NotBranchedOn => (Confidence::Medium, "never decides a revert"),
+            };
+            Finding {
+                id: self.name(),
+                severity: Severity::Medium,
+                confidence,
+                pcs: op(call.pc),
+                related: vec![],
+                message: format!(
+                    "success of {} to {} at {:#x} in {} {}",
+                    operation, call.target, call.pc, function(call.selector), fate,
+                ),
+            }
+        }).collect()
+    }
+}
+
+/// `Program::environment_dependence`.
+pub struct EnvironmentDependenceDetector;
+
+impl Detector for EnvironmentDependenceDetector {
+    fn name(&self) -> &'static str {
+        "environment-dependence"
+    }
+
+    fn description(&self) -> &'static str {
+        "tx.origin authenticates the caller, or block values decide a branch, call value or storage write."
+    }
+
+    fn detect(&self, program: &Program) -> Vec<Finding> {
+        program.environment_dependence().iter().map(|dependence| {
+            let (severity, confidence, usage) = match dependence.usage {
+                DependenceUse::Authentication => (Severity::Medium, Confidence::High, "authentication"),
+                DependenceUse::Randomness => (Severity::High, Confidence::Medium, "randomness"),
+                DependenceUse::Data => (Severity::Low, Confidence::Low, "data"),
+            };
+            Finding {
+                id: self.name(),
+                severity,
+                confidence,
+                pcs: op(dependence.sink_pc),
+                related: vec![RelatedLocation::at(dependence.source_pc, "source")],
+                message: format!(
+                    "{:?} read at {:#x} is used as {} by the {:?} at {:#x} in {}",
+                    dependence.source, dependence.source_pc, usage, dependence.sink, dependence.sink_pc,
+                    function(dependence.selector),
+                ),
+            }
+        }).collect()
+    }
+}
@@ -0,0 +1,324 @@
+use std::collections::{BTreeSet, VecDeque};
+use petgraph::stable_graph::NodeIndex;
+use primitive_types::U256;
+use revm::opcode::*;
+use crate::op::{OpType, Operation};
+use crate::vyper::JumpTableKind;
+use crate::{Block, BlockKind, Program};
+
+/// An external function reached through the selector dispatcher.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExternalFunction {
+    pub selector: [u8; 4],
+    /// The JUMPDEST the dispatcher jumps to on a matching selector.
+    pub entry: U256,
+    /// pc of the JUMPI comparing against `selector`.
+    pub dispatch_pc: U256,
+    /// False if the entry, or the dispatcher before it, rejects a non-zero CALLVALUE.
+    pub payable: bool,
+    /// Blocks reachable from `entry` in the current CFG, shared ones included.
+    pub blocks: BTreeSet<NodeIndex>,
+}
+
+/// The selector dispatcher at the start of a solc contract.
+#[derive(Debug, Clone, Default)]
+pub struct Dispatcher {
+    /// Sorted by selector.
+    pub functions: Vec<ExternalFunction>,
+    /// Where calls matching no selector go, unless that just reverts.
+    pub fallback: Option<U256>,
+    /// Where calls with empty calldata go, if that differs from `fallback`.
+    pub receive: Option<U256>,
+    /// The comparison, split and guard blocks making up the dispatcher itself.
+    pub blocks: BTreeSet<NodeIndex>,
+}
+
+impl Dispatcher {
+    /// Recognizes the linear `DUP1 PUSH4 EQ PUSH JUMPI` chain (or the older
+    /// `PUSH4 DUP2 EQ`), the optimizer's binary search splitting on GT/LT, the
+    /// short calldata check, CALLVALUE guards and Vyper's selector tables.
+    /// Only the code layout is used, so this works before any edges are
+    /// generated, except for `ExternalFunction::blocks`.
+    pub fn new(program: &Program) -> Self {
+        let mut dispatcher = Dispatcher::default();
+        let start = match program.block_idx_at(0) {
+            Some(start) => start,
+            None => return dispatcher,
+        };
+        let mut guarded = false;
+        let mut no_match = BTreeSet::new();
+        // pc and no-match side of the last selector comparison in the chain.
+        let mut last_compare: Option<(U256, Option<NodeIndex>)> = None;
+        let mut queue = VecDeque::from([start]);
+        while let Some(node) = queue.pop_front() {
+            if !dispatcher.blocks.insert(node) {
+                continue;
+            }
+            let block = &program.cfg[node];
+            let fallthrough = program.fallthrough_of(node);
+            if let Some(table) = program.jump_tables.iter().find(|table| table.header_block == block.id()) {
+                // Vyper: dense tables hold the selectors, sparse ones lead to
+                // buckets of ordinary comparisons.
+                dispatcher.functions.extend(table.selectors.iter().map(|(selector, label)| ExternalFunction {
+                    selector: *selector,
+                    entry: *label,
+                    dispatch_pc: table.codecopy_pc,
+                    payable: true,
+                    blocks: BTreeSet::new(),
+                }));
+                if table.kind == JumpTableKind::Sparse {
+                    queue.extend(table.targets.iter().filter_map(|target| program.block_idx_at(target.as_usize())));
+                }
+            } else if let Some(compare) = selector_compare(program, node) {
+                if last_compare.map_or(true, |(pc, _)| compare.pc > pc) {
+                    last_compare = Some((compare.pc, compare.next));
+                }
+                dispatcher.functions.push(ExternalFunction {
+                    selector: compare.selector,
+                    entry: compare.entry,
+                    dispatch_pc: compare.pc,
+                    payable: true,
+                    blocks: BTreeSet::new(),
+                });
+                queue.extend(compare.next);
+            } else if let Some(target) = selector_split(program, block) {
+                queue.extend(fallthrough);
+                queue.extend(program.block_idx_at(target.as_usize()));
+            } else if let Some(next) = callvalue_guard(program, node) {
+                guarded = true;
+                queue.push_back(next);
+            } else if let Some(short) = short_calldata_check(program, node) {
+                queue.extend(fallthrough);
+                queue.extend(short);
+            } else if let Some(next) = trampoline(program, node) {
+                queue.push_back(next);
+            } else {
+                dispatcher.blocks.remove(&node);
+                no_match.insert(node);
+            }
+        }
+
+        // Short calldata and unknown selectors can land in different places;
+        // the one telling empty calldata apart covers both receive and fallback.
+        let receive_split = no_match.iter()
+            .find_map(|node| receive_check(program, &program.cfg[*node]).map(|fallback| (*node, fallback)));
+        // Otherwise calls matching no selector end up where the last
+        // comparison sends them, past any trampolines.
+        let chain_end = last_compare.and_then(|(_, next)| next)
+            .and_then(|next| trampoline_chain(program, next).into_iter().find(|node| no_match.contains(node)));
+        let fallback_and_receive = match receive_split {
+            Some((node, fallback)) => Some((fallback, program.fallthrough_of(node))),
+            None => chain_end.map(|node| (Some(node), None)),
+        };
+        if let Some((fallback, receive)) = fallback_and_receive {
+            let entry = |node: Option<NodeIndex>| node
+                .filter(|node| !aborts(program, *node))
+                .map(|node| program.cfg[node].id());
+            dispatcher.fallback = entry(fallback);
+            dispatcher.receive = entry(receive);
+        }
+
+        dispatcher.functions.sort_by_key(|function| function.selector);
+        dispatcher.functions.dedup_by_key(|function| function.selector);
+        let dispatch_blocks = dispatcher.blocks.clone();
+        dispatcher.functions.iter_mut().for_each(|function| {
+            let entry = match program.block_idx_at(function.entry.as_usize()) {
+                Some(entry) => entry,
+                None => return,
+            };
+            function.payable = !guarded && callvalue_guard(program, entry).is_none();
+            collect_blocks(program, entry, &dispatch_blocks, &mut function.blocks);
+        });
+        dispatcher
+    }
+
+    /// Blocks reachable from `fallback` and `receive` in the current CFG,
+    /// not going back through the dispatcher. Without any external function,
+    /// every call runs the code from pc 0, so that is walked instead.
+    pub fn fallback_blocks(&self, program: &Program) -> BTreeSet<NodeIndex> {
+        let entries = if self.functions.is_empty() && self.fallback.is_none() && self.receive.is_none() {
+            vec![U256::zero()]
+        } else {
+            self.fallback.into_iter().chain(self.receive).collect()
+        };
+        let mut blocks = BTreeSet::new();
+        entries.iter()
+            .filter_map(|entry| program.block_idx_at(entry.as_usize()))
+            .for_each(|entry| collect_blocks(program, entry, &self.blocks, &mut blocks));
+        blocks
+    }
+
+    pub fn entry_of(&self, selector: [u8; 4]) -> Option<U256> {
+        self.functions.iter().find(|function| function.selector == selector).map(|function| function.entry)
+    }
+
+    /// External functions whose code includes `node`.
+    pub fn functions_containing(&self, node: NodeIndex) -> impl Iterator<Item = &ExternalFunction> + '_ {
+        self.functions.iter().filter(move |function| function.blocks.contains(&node))
+    }
+
+    /// Selectors of the external functions whose code includes all of
+    /// `nodes`, or a lone `None` when there is no such function, so that a
+    /// finding outside every recovered function is still reported once.
+    pub fn selectors_containing(&self, nodes: &[NodeIndex]) -> Vec<Option<[u8; 4]>> {
+        let selectors = self.functions.iter()
+            .filter(|function| !nodes.is_empty() && nodes.iter().all(|node| function.blocks.contains(node)))
+            .map(|function| Some(function.selector))
+            .collect::<Vec<_>>();
+        if selectors.is_empty() { vec![None] } else { selectors }
+    }
+}
+
+/// An instruction pattern matched against the end of a block; `None` matches any PUSH.
+fn ends_with<'a>(block: &'a Block, pattern: &[Option<u8>]) -> Option<&'a [Operation]> {
+    let tail = block.ops.get(block.ops.len().checked_sub(pattern.len())?..)?;
+    tail.iter().zip(pattern).all(|(op, expected)| match expected {
+        Some(code) => op.code.u8() == *code,
+        None => op.category() == OpType::Push,
+    }).then(|| tail)
+}
+
+struct SelectorCompare {
+    selector: [u8; 4],
+    entry: U256,
+    /// pc of the JUMPI.
+    pc: U256,
+    /// Where the chain goes on when the selector does not match.
+    next: Option<NodeIndex>,
+}
+
+/// The selector being pushed and compared with a copy of the calldata selector
+/// at the end of `block`: `DUPn PUSH4 sel EQ` as solc emits it, or
+/// `PUSH4 sel DUPn EQ` as older solc and Vyper do.
+fn compared_selector<'a>(block: &'a Block, cmp: u8, then: &[Option<u8>]) -> Option<(&'a Operation, &'a [Operation])> {
+    let is_dup = |op: &Operation| op.category() == OpType::Dup;
+    let len = 3 + then.len();
+    let tail = block.ops.get(block.ops.len().checked_sub(len)?..)?;
+    let rest = ends_with(block, then)?;
+    if tail[2].code.u8() != cmp {
+        return None;
+    }
+    let selector_op = match (&tail[0], &tail[1]) {
+        (dup, push) if is_dup(dup) && push.category() == OpType::Push => push,
+        (push, dup) if is_dup(dup) && push.category() == OpType::Push => push,
+        _ => return None,
+    };
+    (selector_op.arg_size <= 4).then(|| (selector_op, rest))
+}
+
+/// A selector comparison jumping to its entry on a match, or Vyper's
+/// `EQ ISZERO PUSH skip JUMPI` followed by a `PUSH entry JUMP` block.
+fn selector_compare(program: &Program, node: NodeIndex) -> Option<SelectorCompare> {
+    let block = &program.cfg[node];
+    let fallthrough = program.fallthrough_of(node);
+    if let Some((selector_op, rest)) = compared_selector(block, EQ, &[None, Some(JUMPI)]) {
+        return Some(SelectorCompare {
+            selector: selector_op.push_value(&program.code)?.low_u32().to_be_bytes(),
+            entry: rest[0].push_value(&program.code)?,
+            pc: rest[1].pc?,
+            next: fallthrough,
+        });
+    }
+    let (selector_op, rest) = compared_selector(block, EQ, &[Some(ISZERO), None, Some(JUMPI)])?;
+    let goto = ends_with(&program.cfg[fallthrough?], &[None, Some(JUMP)])?;
+    Some(SelectorCompare {
+        selector: selector_op.push_value(&program.code)?.low_u32().to_be_bytes(),
+        entry: goto[0].push_value(&program.code)?,
+        pc: goto[1].pc?,
+        next: program.block_idx_at(rest[1].push_value(&program.code)?.as_usize()),
+    })
+}
+
+/// `DUP1 PUSH4 sel GT/LT PUSH dest JUMPI`, a branch of the binary search over selectors.
+fn selector_split(program: &Program, block: &Block) -> Option<U256> {
+    [GT, LT].iter().find_map(|cmp| {
+        let (_, rest) = compared_selector(block, *cmp, &[None, Some(JUMPI)])?;
+        rest[0].push_value(&program.code)
+    })
+}
+
+/// A JUMPI on CALLVALUE where one side aborts; returns the side that goes on.
+fn callvalue_guard(program: &Program, node: NodeIndex) -> Option<NodeIndex> {
+    let block = &program.cfg[node];
+    if !block.ops.iter().any(|op| op.code.u8() == CALLVALUE) {
+        return None;
+    }
+    let (taken, fallthrough) = conditional_branches(program, node)?;
+    if aborts(program, fallthrough) {
+        Some(taken)
+    } else if aborts(program, taken) {
+        Some(fallthrough)
+    } else {
+        None
+    }
+}
+
+/// A JUMPI on CALLDATASIZE right before the selector is loaded, sending
+/// calls too short to hold one elsewhere. Returns the node they go to, if any.
+fn short_calldata_check(program: &Program, node: NodeIndex) -> Option<Option<NodeIndex>> {
+    let block = &program.cfg[node];
+    if !block.ops.iter().any(|op| op.code.u8() == CALLDATASIZE) {
+        return None;
+    }
+    let tail = ends_with(block, &[None, Some(JUMPI)])?;
+    let fallthrough = program.fallthrough_of(node)?;
+    if !program.cfg[fallthrough].ops.iter().any(|op| op.code.u8() == CALLDATALOAD) {
+        return None;
+    }
+    Some(tail[0].push_value(&program.code).and_then(|target| program.block_idx_at(target.as_usize())))
+}
+
+/// `CALLDATASIZE PUSH dest JUMPI`: empty calldata falls through to receive,
+/// anything else jumps to `dest`. Returns the node at `dest`, if any.
+fn receive_check(program: &Program, block: &Block) -> Option<Option<NodeIndex>> {
+    let tail = ends_with(block, &[Some(CALLDATASIZE), None, Some(JUMPI)])?;
+    Some(tail[1].push_value(&program.code).and_then(|target| program.block_idx_at(target.as_usize())))
+}
+
+/// Blocks that only pass control on: a lone JUMPDEST or `PUSH dest JUMP`.
+fn trampoline(program: &Program, node: NodeIndex) -> Option<NodeIndex> {
+    let block = &program.cfg[node];
+    let ops = block.ops.iter().filter(|op| op.code.u8() != JUMPDEST).collect::<Vec<_>>();
+    match ops.as_slice() {
+        [] => program.fallthrough_of(node),
+        [push, jump] if push.category() == OpType::Push && jump.category() == OpType::Jump => {
+            push.push_value(&program.code).and_then(|target| program.block_idx_at(target.as_usize()))
+        },
+        _ => None,
+    }
+}
+
+/// `node` followed by the blocks its trampolines pass control on to, up to
+/// the first block that does something else, or that the chain already visited.
+fn trampoline_chain(program: &Program, node: NodeIndex) -> Vec<NodeIndex> {
+    let mut chain = vec![node];
+    while let Some(next) = trampoline(program, chain[chain.len() - 1]).filter(|next| !chain.contains(next)) {
+        chain.push(next);
+    }
+    chain
+}
+
+fn conditional_branches(program: &Program, node: NodeIndex) -> Option<(NodeIndex, NodeIndex)> {
+    let block = &program.cfg[node];
+    let tail = ends_with(block, &[None, Some(JUMPI)])?;
+    let taken = program.block_idx_at(tail[0].push_value(&program.code)?.as_usize())?;
+    Some((taken, program.fallthrough_of(node)?))
+}
+
+/// Adds the code blocks reachable from `entry` without entering `dispatch_blocks`.
+fn collect_blocks(program: &Program, entry: NodeIndex, dispatch_blocks: &BTreeSet<NodeIndex>, blocks: &mut BTreeSet<NodeIndex>) {
+    let mut queue = vec![entry];
+    while let Some(node) = queue.pop() {
+        if dispatch_blocks.contains(&node) || !blocks.insert(node) {
+            continue;
+        }
+        queue.extend(program.cfg.neighbors(node).filter(|next| program.cfg[*next].kind == BlockKind::Code));
+    }
+}
+
+/// Whether `node` goes straight to a REVERT or INVALID, possibly through
+/// blocks that only jump on, as solc shares a single `PUSH 0 DUP1 REVERT`.
+pub(crate) fn aborts(program: &Program, node: NodeIndex) -> bool {
+    let end = *trampoline_chain(program, node).last().unwrap();
+    matches!(program.cfg[end].ops.last(), Some(op) if op.is_invalid || [REVERT, INVALID].contains(&op.code.u8()))
+}
@@ -0,0 +1,106 @@
+use std::collections::{BTreeSet, HashMap};
+use petgraph::algo::dominators::{simple_fast, Dominators};
+use petgraph::stable_graph::{NodeIndex, StableDiGraph};
+use petgraph::visit::Reversed;
+use petgraph::Direction;
+use crate::{Block, Program};
+use crate::edge::Edge;
+
+/// Dominator and post-dominator trees of a `Program::cfg`, plus its dominance
+/// frontiers. Nodes unreachable from the entry node (or that cannot reach any
+/// halting node, for post-dominance) have no dominators and dominate nothing.
+#[derive(Debug, Clone)]
+pub struct Dominance {
+    dominators: Dominators<NodeIndex>,
+    post_dominators: Dominators<NodeIndex>,
+    /// The root of `post_dominators`, a node joining every synthetic sink
+    /// that only exists in the reversed copy of the graph.
+    virtual_exit: NodeIndex,
+    frontiers: HashMap<NodeIndex, BTreeSet<NodeIndex>>,
+}
+
+impl Dominance {
+    pub fn new(program: &Program) -> Self {
+        let cfg = &program.cfg;
+        let dominators = simple_fast(cfg, program.synthetic.entry);
+
+        // Post-dominance needs a single root, so join all the sinks.
+        let mut sinks = cfg.map(|_, _| (), |_, _| ());
+        let virtual_exit = sinks.add_node(());
+        let nodes = &program.synthetic;
+        [nodes.exit, nodes.revert, nodes.unresolved, nodes.invalid_jump].iter().for_each(|sink| {
+            sinks.add_edge(*sink, virtual_exit, ());
+        });
+        let post_dominators = simple_fast(Reversed(&sinks), virtual_exit);
+
+        let frontiers = dominance_frontiers(cfg, &dominators);
+        Dominance {
+            dominators,
+            post_dominators,
+            virtual_exit,
+            frontiers,
+        }
+    }
+
+    /// Whether every path from the entry node to `b` passes through `a`.
+    /// Every reachable node dominates itself.
+    pub fn dominates(&self, a: NodeIndex, b: NodeIndex) -> bool {
+        self.dominators.dominators(b).map_or(false, |mut doms| doms.any(|dom| dom == a))
+    }
+
+    /// Whether every path from `b` to a halting node passes through `a`.
+    pub fn post_dominates(&self, a: NodeIndex, b: NodeIndex) -> bool {
+        self.post_dominators.dominators(b).map_or(false, |mut doms| doms.any(|dom| dom == a))
+    }
+
+    pub fn immediate_dominator(&self, node: NodeIndex) -> Option<NodeIndex> {
+        self.dominators.immediate_dominator(node)
+    }
+
+    pub fn immediate_post_dominator(&self, node: NodeIndex) -> Option<NodeIndex> {
+        self.post_dominators.immediate_dominator(node).filter(|idom| *idom != self.virtual_exit)
+    }
+
+    /// Nodes immediately dominated by `node`, i.e. its children in the dominator tree.
+    pub fn dominated_by(&self, node: NodeIndex) -> impl Iterator<Item = NodeIndex> + '_ {
+        self.dominators.immediately_dominated_by(node).filter(move |child| *child != node)
+    }
+
+    /// Nodes where `node`'s dominance ends: successors of nodes it dominates
+    /// that it does not strictly dominate itself.
+    pub fn frontier(&self, node: NodeIndex) -> impl Iterator<Item = NodeIndex> + '_ {
+        self.frontiers.get(&node).into_iter().flat_map(|frontier| frontier.iter().copied())
+    }
+}
+
+/// Dominance frontiers as in Cooper, Harvey and Kennedy's "A Simple, Fast
+/// Dominance Algorithm": walk up from each predecessor of a join node until
+/// reaching the join node's immediate dominator.
+fn dominance_frontiers(
+    cfg: &StableDiGraph<Block, Edge>,
+    dominators: &Dominators<NodeIndex>,
+) -> HashMap<NodeIndex, BTreeSet<NodeIndex>> {
+    let mut frontiers: HashMap<NodeIndex, BTreeSet<NodeIndex>> = HashMap::new();
+    cfg.node_indices().for_each(|node| {
+        let idom = match dominators.immediate_dominator(node) {
+            Some(idom) => idom,
+            None => return,
+        };
+        let preds = cfg.neighbors_directed(node, Direction::Incoming)
+            .filter(|pred| dominators.dominators(*pred).is_some())
+            .collect::<BTreeSet<_>>();
+        if preds.len() < 2 {
+            return;
+        }
+        preds.into_iter().for_each(|mut runner| {
+            while runner != idom {
+                frontiers.entry(runner).or_default().insert(node);
+                runner = match dominators.immediate_dominator(runner) {
+                    Some(next) => next,
+                    None => break,
+                };
+            }
+        });
+    });
+    frontiers
+}
@@ -0,0 +1,93 @@
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+use primitive_types::U256;
+
+/// How control reaches `to` from the last instruction of `from`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum EdgeKind {
+    /// Unconditional JUMP.
+    Jump,
+    /// JUMPI with a non-zero condition.
+    JumpITaken,
+    /// JUMPI with a zero condition, continuing at the next instruction.
+    JumpIFallthrough,
+    /// Straight-line execution into the next block, e.g. a block split at a JUMPDEST.
+    Fallthrough,
+    /// From the synthetic entry node to the block at pc 0.
+    Entry,
+    /// From a halting block to the exit or revert node; `to` is the halting pc.
+    Halt,
+    /// From a jump with no known target to the unresolved node; `to` is the jump pc.
+    Unresolved,
+    /// From a jump to its invalid target's sink node; `to` is the target.
+    InvalidJump,
+}
+
+/// Which pass first discovered an edge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum EdgeProvenance {
+    /// A PUSH immediately followed by JUMP/JUMPI, or the code layout itself.
+    PushJump,
+    /// A target resolved on the symbolic stack.
+    Symbolic,
+    /// Observed in an execution trace.
+    Trace,
+    /// Decoded from a Vyper selector table in the data section.
+    JumpTable,
+    /// Into or out of a synthetic node, from the way a block ends.
+    Synthetic,
+}
+
+/// A CFG edge between the blocks starting at `from` and `to`.
+///
+/// Equality, hashing and ordering only consider `from`, `to` and `kind`, so
+/// the same edge found by several passes is stored once, keeping the
+/// provenance of whichever pass found it first.
+#[derive(Debug, Clone, Copy)]
+pub struct Edge {
+    pub from: U256,
+    pub to: U256,
+    pub kind: EdgeKind,
+    pub provenance: EdgeProvenance,
+}
+
+impl Edge {
+    pub fn new(from: U256, to: U256, kind: EdgeKind, provenance: EdgeProvenance) -> Self {
+        Edge {
+            from,
+            to,
+            kind,
+            provenance,
+        }
+    }
+
+    fn key(&self) -> (U256, U256, EdgeKind) {
+        (self.from, self.to, self.kind)
+    }
+}
+
+impl PartialEq for Edge {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Edge {}
+
+impl Hash for Edge {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
+
+impl PartialOrd for Edge {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Edge {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
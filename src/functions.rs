@@ -0,0 +1,233 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use petgraph::graphmap::DiGraphMap;
+use petgraph::stable_graph::NodeIndex;
+use primitive_types::U256;
+use crate::edge::EdgeKind;
+use crate::op::OpType;
+use crate::context::CallContext;
+use crate::stack::SymbolicStack;
+use crate::{BlockKind, Program};
+
+/// A JUMP into an internal function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct InternalCall {
+    /// pc of the JUMP instruction.
+    pub pc: U256,
+    /// Start of the block containing the jump.
+    pub block: U256,
+    /// Entry of the called function.
+    pub callee: U256,
+    /// Where the callee jumps back to.
+    pub return_address: U256,
+}
+
+/// How a function uses the stack, as solc lays out internal calls: the caller
+/// pushes the return address, then `args`, and gets `returns` values back in
+/// their place.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StackSignature {
+    pub args: usize,
+    pub returns: usize,
+}
+
+/// Blocks reachable from a function entry without going through a return.
+/// Blocks shared between functions, e.g. common revert paths, belong to each of them.
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub entry: U256,
+    pub entry_node: NodeIndex,
+    pub blocks: BTreeSet<NodeIndex>,
+    /// Blocks whose final JUMP returns to the caller.
+    pub exits: BTreeSet<NodeIndex>,
+    /// Calls into this function.
+    pub call_sites: Vec<InternalCall>,
+    /// Entries of the functions containing `call_sites`.
+    pub callers: BTreeSet<U256>,
+    /// Entries of the functions this one calls.
+    pub callees: BTreeSet<U256>,
+    /// `None` for functions that never return, or whose callees' signatures
+    /// could not be worked out, e.g. under recursion.
+    pub signature: Option<StackSignature>,
+}
+
+/// Internal functions recovered from the CFG, keyed by entry pc. The function
+/// at pc 0 is the contract's own entry point: it has no call sites and never returns.
+#[derive(Debug, Clone, Default)]
+pub struct Functions {
+    pub functions: BTreeMap<U256, Function>,
+    /// An edge per caller and callee pair, weighted by the first call's pc.
+    pub call_graph: DiGraphMap<U256, U256>,
+}
+
+impl Functions {
+    pub fn new(program: &Program) -> Self {
+        let start = match program.block_idx_at(0) {
+            Some(start) => start,
+            None => return Functions::default(),
+        };
+        let calls = internal_calls(program);
+        let return_addresses = calls.values()
+            .map(|call| call.return_address)
+            .collect::<BTreeSet<_>>();
+        let mut entries = calls.values()
+            .map(|call| call.callee)
+            .collect::<BTreeSet<_>>();
+        entries.insert(U256::zero());
+
+        // A function's exit height depends on its callees', so walk until they settle.
+        let mut exit_heights: HashMap<U256, isize> = HashMap::new();
+        let mut walks = BTreeMap::new();
+        for _ in 0..=entries.len() {
+            walks = entries.iter().filter_map(|entry| {
+                let node = if entry.is_zero() { start } else { program.block_idx_at(entry.as_usize())? };
+                Some((*entry, walk(program, node, &calls, &return_addresses, &exit_heights)))
+            }).collect::<BTreeMap<_, _>>();
+            let settled = walks.iter()
+                .filter_map(|(entry, walk)| walk.exit_height.map(|height| (*entry, height)))
+                .collect::<HashMap<_, _>>();
+            if settled == exit_heights {
+                break;
+            }
+            exit_heights = settled;
+        }
+
+        let mut functions = walks.into_iter().map(|(entry, walk)| {
+            let signature = walk.exit_height.and_then(|exit_height| {
+                // The deepest slot touched is the return address, right below the args.
+                let args = walk.deepest.checked_sub(1)?;
+                let returns = usize::try_from(exit_height + args as isize + 1).ok()?;
+                Some(StackSignature { args, returns })
+            });
+            (entry, Function {
+                entry,
+                entry_node: walk.entry_node,
+                blocks: walk.blocks,
+                exits: walk.exits,
+                call_sites: vec![],
+                callers: BTreeSet::new(),
+                callees: BTreeSet::new(),
+                signature: if entry.is_zero() { None } else { signature },
+            })
+        }).collect::<BTreeMap<_, _>>();
+
+        let mut call_graph = DiGraphMap::new();
+        functions.keys().for_each(|entry| {
+            call_graph.add_node(*entry);
+        });
+        calls.values().for_each(|call| {
+            let block = program.block_idx_at(call.block.as_usize());
+            let callers = functions.values()
+                .filter(|function| block.map_or(false, |block| function.blocks.contains(&block)))
+                .map(|function| function.entry)
+                .collect::<Vec<_>>();
+            callers.iter().for_each(|caller| {
+                if !call_graph.contains_edge(*caller, call.callee) {
+                    call_graph.add_edge(*caller, call.callee, call.pc);
+                }
+                if let Some(function) = functions.get_mut(caller) {
+                    function.callees.insert(call.callee);
+                }
+            });
+            if let Some(callee) = functions.get_mut(&call.callee) {
+                callee.call_sites.push(*call);
+                callee.callers.extend(callers);
+            }
+        });
+        Functions { functions, call_graph }
+    }
+
+    /// Entry pcs of the functions containing `node`.
+    pub fn containing(&self, node: NodeIndex) -> impl Iterator<Item = U256> + '_ {
+        self.functions.values()
+            .filter(move |function| function.blocks.contains(&node))
+            .map(|function| function.entry)
+    }
+}
+
+/// Blocks ending in a JUMP that leaves a return address on the stack, keyed
+/// by block node. Only resolved targets count, so this runs on the finished CFG.
+fn internal_calls(program: &Program) -> BTreeMap<NodeIndex, InternalCall> {
+    program.block_nodes().filter_map(|node| {
+        let block = &program.cfg[node];
+        let jump = block.ops.last().filter(|op| op.category() == OpType::Jump)?;
+        let stack = block.exec_symbolic(SymbolicStack::new(), &program.code, block.ops.len());
+        let mut targets = program.cfg.edges(node)
+            .filter(|edge| edge.weight().kind == EdgeKind::Jump)
+            .map(|edge| edge.weight().to);
+        // Returns are also plain jumps, to whatever address the caller left.
+        let callee = targets.next().filter(|_| targets.next().is_none())?;
+        let return_address = program.return_address([block], callee, &stack, &CallContext::default())?;
+        Some((node, InternalCall {
+            pc: jump.pc?,
+            block: block.id(),
+            callee,
+            return_address,
+        }))
+    }).collect()
+}
+
+struct Walk {
+    entry_node: NodeIndex,
+    blocks: BTreeSet<NodeIndex>,
+    exits: BTreeSet<NodeIndex>,
+    /// Stack height after the return jump, relative to the height at entry.
+    exit_height: Option<isize>,
+    /// How many slots below the entry height the function reads.
+    deepest: usize,
+}
+
+/// Collects a function's blocks, stepping over the calls it makes and
+/// tracking the stack height relative to its entry.
+fn walk(
+    program: &Program,
+    entry_node: NodeIndex,
+    calls: &BTreeMap<NodeIndex, InternalCall>,
+    return_addresses: &BTreeSet<U256>,
+    exit_heights: &HashMap<U256, isize>,
+) -> Walk {
+    let mut result = Walk {
+        entry_node,
+        blocks: BTreeSet::new(),
+        exits: BTreeSet::new(),
+        exit_height: None,
+        deepest: 0,
+    };
+    let mut queue = VecDeque::from([(entry_node, 0_isize)]);
+    while let Some((node, mut height)) = queue.pop_front() {
+        if !result.blocks.insert(node) {
+            continue;
+        }
+        let block = &program.cfg[node];
+        block.ops.iter().for_each(|op| {
+            let reads = match op.category() {
+                OpType::Dup => (op.code.u8() - revm::opcode::DUP1 + 1) as isize,
+                OpType::Swap => (op.code.u8() - revm::opcode::SWAP1 + 2) as isize,
+                _ => op.rm_stack_count as isize,
+            };
+            if reads > height {
+                result.deepest = result.deepest.max((reads - height) as usize);
+            }
+            height += op.add_stack_count as isize - op.rm_stack_count as isize;
+        });
+
+        if let Some(call) = calls.get(&node) {
+            let resume = program.block_idx_at(call.return_address.as_usize());
+            if let (Some(resume), Some(callee_height)) = (resume, exit_heights.get(&call.callee)) {
+                queue.push_back((resume, height + callee_height));
+            }
+            continue;
+        }
+        let successors = program.cfg.neighbors(node)
+            .filter(|next| program.cfg[*next].kind == BlockKind::Code)
+            .collect::<Vec<_>>();
+        let returns = block.ops.last().map_or(false, |op| op.category() == OpType::Jump)
+            && successors.iter().any(|next| return_addresses.contains(&program.cfg[*next].id()));
+        if returns {
+            result.exits.insert(node);
+            result.exit_height.get_or_insert(height);
+            continue;
+        }
+        successors.into_iter().for_each(|next| queue.push_back((next, height)));
+    }
+    result
+}
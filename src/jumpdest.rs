@@ -0,0 +1,62 @@
+use primitive_types::U256;
+use revm::opcode::*;
+
+/// Marks every byte of `code` that is a JUMPDEST instruction, as opposed to a
+/// 0x5b byte inside PUSH immediate data. Matches the interpreter's own
+/// analysis: undefined opcodes are one byte long and a PUSH truncated by the
+/// end of the code swallows the rest of it.
+pub fn jumpdest_map(code: &[u8]) -> Vec<bool> {
+    let mut map = vec![false; code.len()];
+    let mut ptr = 0;
+    while ptr < code.len() {
+        let byte = code[ptr];
+        if byte == JUMPDEST {
+            map[ptr] = true;
+        }
+        if (PUSH1..=PUSH32).contains(&byte) {
+            ptr += (byte - PUSH1) as usize + 1;
+        }
+        ptr += 1;
+    }
+    map
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum InvalidJumpReason {
+    /// The target is past the end of the code.
+    OutOfBounds,
+    /// The target is an instruction other than JUMPDEST.
+    NotJumpdest,
+    /// The target is a 0x5b byte inside a PUSH immediate.
+    InsidePushData,
+    /// The target is a 0x5b byte inside the CBOR metadata trailer. The
+    /// interpreter would accept it, but compilers never jump there and the
+    /// trailer is not disassembled.
+    IntoMetadata,
+}
+
+/// A jump whose resolved target would make the interpreter halt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct InvalidJump {
+    /// pc of the JUMP/JUMPI instruction.
+    pub pc: U256,
+    /// Start of the block containing the jump.
+    pub block: U256,
+    pub target: U256,
+    pub reason: InvalidJumpReason,
+}
+
+/// Why jumping to `target` would fail, or `None` if it is a valid JUMPDEST.
+pub fn invalid_jump_reason(code: &[u8], jumpdests: &[bool], target: U256) -> Option<InvalidJumpReason> {
+    if target >= U256::from(code.len()) {
+        return Some(InvalidJumpReason::OutOfBounds);
+    }
+    let target = target.as_usize();
+    if jumpdests[target] {
+        None
+    } else if code[target] == JUMPDEST {
+        Some(InvalidJumpReason::InsidePushData)
+    } else {
+        Some(InvalidJumpReason::NotJumpdest)
+    }
+}
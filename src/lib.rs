@@ -1,33 +1,149 @@
 extern crate core;
 
 pub mod op_data;
-mod stack;
-mod op;
+pub mod config;
+pub mod stack;
+pub mod op;
+mod context;
+pub mod edge;
+pub mod jumpdest;
+pub mod dominance;
+pub mod loops;
+pub mod functions;
+pub mod dispatcher;
+pub mod vyper;
+pub mod coverage;
+pub mod children;
+pub mod ssa;
+pub mod dataflow;
+pub mod taint;
+pub mod reentrancy;
+pub mod access;
+pub mod arbitrary;
+pub mod unchecked;
+pub mod dependence;
+pub mod detector;
 use op::*;
 use stack::*;
+use config::*;
+use context::*;
+use edge::*;
+use jumpdest::*;
+use dominance::*;
+use loops::*;
+use functions::*;
+use dispatcher::*;
+use vyper::*;
+use coverage::*;
+use children::*;
+use ssa::*;
+use dataflow::*;
+use taint::*;
+use reentrancy::*;
+use access::*;
+use arbitrary::*;
+use unchecked::*;
+use dependence::*;
+use detector::*;
 
-use std::collections::{HashMap, VecDeque, HashSet};
+use std::cell::OnceCell;
+use std::collections::{BTreeSet, HashMap, VecDeque, HashSet};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+use std::time::Instant;
 use std::fmt::Formatter;
 use op_data::*;
 use bytes::Bytes;
 use revm::opcode::*;
 use revm::{opcode::*};
 use ethers_solc::{project, output, contracts, artifacts};
-use petgraph::data::{Element, FromElements};
 use primitive_types::{H256, U256};
 
-use petgraph::graph::{DiGraph, Node};
+use petgraph::graph::Node;
 use petgraph::dot::{Dot, Config};
-use petgraph::{Directed, Graph};
+use petgraph::stable_graph::{NodeIndex, StableDiGraph};
+use petgraph::{Direction, Graph};
 
 pub const MAX_STACK_DEPTH: u16 = 1024;
 
 #[derive(Debug, Default)]
 pub struct Program {
     pub code: Vec<u8>,
-    pub blocks: Vec<Block>,
-    pub edges: Vec<(U256, U256)>,
+    /// Every code block in pc order, followed by the synthetic nodes.
+    pub cfg: StableDiGraph<Block, Edge>,
+    pub synthetic: SyntheticNodes,
+    /// Edges between code blocks found so far; `cfg` is rebuilt from these by `link_blocks`.
+    pub edges: BTreeSet<Edge>,
     pub start_addresses: Vec<U256>,
+    pub report: AnalysisReport,
+    /// `jumpdests[pc]` is true iff `pc` is a JUMPDEST instruction.
+    pub jumpdests: Vec<bool>,
+    pub invalid_jumps: BTreeSet<InvalidJump>,
+    /// Vyper selector tables found in the data section.
+    pub jump_tables: Vec<JumpTable>,
+    /// The CBOR metadata trailer, which is not disassembled.
+    pub metadata: Option<Range<usize>>,
+    /// Node of the code block covering each byte of `code`.
+    block_map: Vec<Option<NodeIndex>>,
+    /// Results derived from `cfg`, dropped whenever its edges change.
+    analyses: Analyses,
+}
+
+/// Analyses of `Program::cfg`, each computed on first use. `link_blocks`
+/// replaces the whole set whenever the edges change.
+#[derive(Debug, Default)]
+struct Analyses {
+    /// Dominator trees.
+    dominance: OnceCell<Dominance>,
+    /// Loop nesting forest.
+    loop_info: OnceCell<LoopInfo>,
+    /// Internal functions and call graph.
+    functions: OnceCell<Functions>,
+    /// Selector dispatcher; it records reachable blocks.
+    dispatcher: OnceCell<Dispatcher>,
+    /// Byte classification.
+    coverage: OnceCell<Coverage>,
+    /// Contracts deployed from embedded initcode.
+    children: OnceCell<Vec<ChildContract>>,
+    /// SSA form.
+    ssa: OnceCell<SsaProgram>,
+    /// Flows from attacker-controlled sources to sinks.
+    taint_flows: OnceCell<Vec<TaintFlow>>,
+    /// Storage writes after re-entrant calls.
+    reentrancy: OnceCell<Vec<Reentrancy>>,
+    /// Privileged operations and their guards.
+    access_control: OnceCell<Vec<AccessControl>>,
+    /// Calldata-controlled jump targets and storage slots.
+    controlled_sites: OnceCell<Vec<ControlledSite>>,
+    /// Calls whose success never decides a revert.
+    unchecked_calls: OnceCell<Vec<UncheckedCall>>,
+    /// Environment values reaching sinks.
+    environment_dependence: OnceCell<Vec<EnvironmentDependence>>,
+}
+
+/// The nodes of `Program::cfg` that do not correspond to code.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SyntheticNodes {
+    pub entry: NodeIndex,
+    pub exit: NodeIndex,
+    pub revert: NodeIndex,
+    pub unresolved: NodeIndex,
+    pub invalid_jump: NodeIndex,
+}
+
+/// A pending path in the symbolic pass: the block to run next, the stack, its
+/// possible return targets and the call context it starts with and the
+/// (from, to) block nodes taken to get there.
+struct PathState {
+    block: NodeIndex,
+    stack: SymbolicStackCapture,
+    targets: SymbolicStackCapture<ReturnTargets>,
+    context: CallContext,
+    trail: Vec<(NodeIndex, NodeIndex)>,
+    /// Length of `trail` when `context` last changed: the edges after it
+    /// were taken in the current call frame.
+    frame: usize,
 }
 
 pub type CfgNode = Node<CfgNodeData, u64>;
@@ -37,6 +153,8 @@ impl Program {
         let mut entry_point: U256 = U256::zero();
 
         let code = code[entry_point.as_usize() .. code.len()].to_vec();
+        let metadata = metadata_range(&code);
+        let code_end = metadata.as_ref().map_or(code.len(), |metadata| metadata.start);
 
         let mut blocks = vec![];
 
@@ -45,7 +163,7 @@ impl Program {
         let mut prev_ptr = ptr;
         let mut curr_block_codes = vec![];
         let mut entry_points = vec![];
-        while ptr < code.len(){
+        while ptr < code_end {
             let curr_byte = code[ptr];
             if let Some(opcode) = OpCode::try_from_u8(curr_byte) {
 
@@ -55,7 +173,7 @@ impl Program {
                 let curr_op_with_metadata = Operation::from(curr_op).pc(ptr.into());
                 curr_block_codes.push(curr_op_with_metadata);
                 if BLOCK_END_INSTRUCTIONS.contains(&u8_code) ||
-                    (ptr + ptr_inc_size) >= code.len() - 1 ||
+                    (ptr + ptr_inc_size) >= code_end - 1 ||
 
                     (code[ptr + ptr_inc_size] == JUMPDEST)
                 {
@@ -66,7 +184,8 @@ impl Program {
                         pc_end: ptr,
                         ops: curr_block_codes.clone(),
                         successors: vec![],
-
+                        predecessors: vec![],
+                        kind: BlockKind::Code,
                     };
                     blocks.push(block);
                     curr_block_codes = vec![];
@@ -82,21 +201,26 @@ impl Program {
 
                 println!("Could not derive OpCode from {:?}", hex::encode(&[curr_byte]));
                 println!("Opcodes in this block: {:?}", curr_block_codes);
-                entry_points.push(U256::from(prev_ptr));
-                let block = Block {
-                    pc_start: prev_ptr,
-                    pc_end: ptr - 1,
-                    ops: curr_block_codes.clone(),
-                    successors: vec![],
-
-                };
-                blocks.push(block);
+                if !curr_block_codes.is_empty() {
+                    entry_points.push(U256::from(prev_ptr));
+                    let block = Block {
+                        pc_start: prev_ptr,
+                        pc_end: curr_block_codes.last().unwrap().pc.unwrap().as_usize(),
+                        ops: curr_block_codes.clone(),
+                        successors: vec![],
+                        predecessors: vec![],
+                        kind: BlockKind::Code,
+                    };
+                    blocks.push(block);
+                }
                 curr_block_codes = vec![];
                 let invalid_block = Block {
                     pc_start: ptr,
                     pc_end: ptr,
                     ops: vec![Operation::invalid(curr_byte, Some(ptr.into()))],
-                    successors: vec![]
+                    successors: vec![],
+                    predecessors: vec![],
+                    kind: BlockKind::Code,
                 };
                 entry_points.push(U256::from(ptr));
                 ptr += 1;
@@ -105,70 +229,328 @@ impl Program {
 
             }
         }
-        Program {
+        let mut cfg = StableDiGraph::new();
+        let mut block_map = vec![None; code.len()];
+        blocks.into_iter().for_each(|block| {
+            let arg_size = block.ops.last().map_or(0, |op| op.arg_size as usize);
+            let end = (block.pc_end + arg_size).min(code.len() - 1);
+            let (start, node) = (block.pc_start, cfg.add_node(block));
+            (start..=end).for_each(|pc| block_map[pc] = Some(node));
+        });
+        let synthetic = SyntheticNodes {
+            entry: cfg.add_node(Block::synthetic(BlockKind::Entry)),
+            exit: cfg.add_node(Block::synthetic(BlockKind::Exit)),
+            revert: cfg.add_node(Block::synthetic(BlockKind::Revert)),
+            unresolved: cfg.add_node(Block::synthetic(BlockKind::Unresolved)),
+            invalid_jump: cfg.add_node(Block::synthetic(BlockKind::InvalidJump)),
+        };
+        let mut program = Program {
+            jumpdests: jumpdest_map(&code),
+            block_map,
             code,
-            blocks,
+            cfg,
+            synthetic,
             start_addresses: entry_points,
-            edges: vec![]
-        }
+            edges: BTreeSet::new(),
+            report: AnalysisReport::default(),
+            invalid_jumps: BTreeSet::new(),
+            jump_tables: vec![],
+            metadata,
+            analyses: Analyses::default(),
+        };
+        program.link_blocks();
+        program.jump_tables = jump_tables(&program);
+        program
     }
 
     pub fn gen_symbolic_edges(&mut self) {
-        // Depth first search through CFG.
-        // Only consider SWAP, DUP, PUSH, AND, and POP
+        self.gen_symbolic_edges_with_config(&AnalysisConfig::default());
+    }
+
+    /// Explores the program from its first block, running each path on a
+    /// `SymbolicStack` and adding an edge for every jump whose target is a
+    /// known constant. Exploration stops early once any limit in `config` is hit.
+    pub fn gen_symbolic_edges_with_config(&mut self, config: &AnalysisConfig) -> &AnalysisReport {
+        let started = Instant::now();
+        let mut report = AnalysisReport::default();
+        let first_block = match self.block_idx_at(0) {
+            Some(node) => node,
+            None => {
+                self.report = report;
+                return &self.report;
+            }
+        };
 
-        // let depth = 1;
-        // let mut curr_depth = 0;
-        let mut visited: HashSet<(usize, usize)> = HashSet::new();
+        // Stack and return targets reaching each (block, call context, stack
+        // hash) key; the hash is only non-zero in path-sensitive mode,
+        // otherwise stacks are joined.
+        let mut states: HashMap<(NodeIndex, CallContext, u64), (SymbolicStackCapture, SymbolicStackCapture<ReturnTargets>)> = HashMap::new();
+        let mut reached_jumps: HashSet<NodeIndex> = HashSet::new();
+        let mut resolved_jumps: HashSet<NodeIndex> = HashSet::new();
+        let mut unknown_jumps: HashSet<NodeIndex> = HashSet::new();
+        let mut cut: HashMap<NodeIndex, AnalysisLimit> = HashMap::new();
+        let mut new_edges = vec![];
+        let mut new_invalid_jumps = vec![];
         let mut queue = VecDeque::new();
-        let mut stack = SymbolicStack::new();
-        queue.push_front((self.blocks.first().unwrap().clone(), stack.capture()));
-         while !queue.is_empty() {
-            
-            let (mut curr_block, curr_stack) = queue.pop_back().unwrap();
-            let mut stack = curr_block.exec_symbolic(SymbolicStack::from(curr_stack), &self.code, curr_block.ops.len() - 1);
-            let last_block_op = curr_block.ops.last().unwrap();
-            println!("LAST BLOCK OP {:?}", last_block_op);
-            if last_block_op.category() == OpType::Jump {
-                 let stack_top = stack.peek();
-                 println!("STACK TOP: {:?}", stack_top);
-                 match stack_top {
-                     SymbolicStackValue::Data(data) => {
-        //                 // An edge in CFG discovered
-                         if let Some(next_block) = self.blocks.iter().find(|blk| {
-                             blk.id() == U256::from_big_endian(data.as_slice())
-                         }) {
-                            println!("Block {} Points to {}", curr_block.id(), next_block.id());
-        //                    curr_block.successors.push(next_block.id().as_usize());
-                              stack.execute(curr_block.ops.last().unwrap(), &self.code);
-                            let edge = (curr_block.id().as_usize(), next_block.id().as_usize());
-                            if !visited.contains(&(edge)) {
-        //                         visited.insert(edge);
-                                 queue.push_front((next_block.clone(), stack.capture()));
-        //                         self.edges.push((edge.0.into(), edge.1.into()));
-                                
-                            }
-        //                    curr_depth += 1;
-        //                 } else {
-        //                     panic!("Invalid jump dest found in symbolic block");
-                         }
-                     },
-                     _ => {}
-                 }
+        queue.push_front(PathState {
+            block: first_block,
+            stack: SymbolicStack::new().capture(),
+            targets: SymbolicStack::default().capture(),
+            context: CallContext::default(),
+            trail: vec![],
+            frame: 0,
+        });
 
+        while let Some(state) = queue.pop_back() {
+            let global_limit = if config.timeout.map_or(false, |timeout| started.elapsed() >= timeout) {
+                Some(AnalysisLimit::Timeout)
+            } else if report.states_explored >= config.max_states {
+                Some(AnalysisLimit::MaxStates)
+            } else {
+                None
+            };
+            if let Some(limit) = global_limit {
+                report.limits_hit.insert(limit);
+                cut.entry(state.block).or_insert(limit);
+                queue.iter().for_each(|pending| {
+                    cut.entry(pending.block).or_insert(limit);
+                });
+                break;
+            }
+            report.states_explored += 1;
+
+            let block = &self.cfg[state.block];
+            let mut stack = SymbolicStack::from(state.stack);
+            let mut return_targets = SymbolicStack::from(state.targets);
+            let mut successors = vec![];
+            match block.ops.last() {
+                Some(last_op) if matches!(last_op.category(), OpType::Jump | OpType::JumpI) => {
+                    stack = block.exec_symbolic(stack, &self.code, block.ops.len() - 1);
+                    return_targets = block.exec_symbolic(return_targets, &self.code, block.ops.len() - 1);
+                    reached_jumps.insert(state.block);
+                    // A target joined from several callers under one context is
+                    // unknown on the stack, but each of them is still kept.
+                    let targets = match stack.peek().as_u256() {
+                        Some(target) => vec![target],
+                        None => return_targets.peek().0.map_or(vec![], |targets| targets.into_iter().collect()),
+                    };
+                    stack.execute(last_op, &self.code);
+                    return_targets.execute(last_op, &self.code);
+                    targets.iter().for_each(|target| {
+                        if let Some(reason) = self.invalid_target_reason(*target) {
+                            resolved_jumps.insert(state.block);
+                            new_invalid_jumps.push(InvalidJump {
+                                pc: last_op.pc.unwrap(),
+                                block: block.id(),
+                                target: *target,
+                                reason,
+                            });
+                        } else if let Some(next) = self.block_idx_at(target.as_usize()) {
+                            resolved_jumps.insert(state.block);
+                            let kind = if last_op.category() == OpType::JumpI {
+                                EdgeKind::JumpITaken
+                            } else {
+                                EdgeKind::Jump
+                            };
+                            new_edges.push(Edge::new(block.id(), self.cfg[next].id(), kind, EdgeProvenance::Symbolic));
+                            let context = if last_op.category() == OpType::JumpI {
+                                state.context.clone()
+                            } else {
+                                self.call_context_after_jump(block, *target, &stack, &state.context, &state.trail[state.frame..], config)
+                            };
+                            successors.push((next, context));
+                        }
+                    });
+                    if targets.is_empty() {
+                        let table_targets = if last_op.category() == OpType::Jump {
+                            self.jump_table_targets(state.block, &state.trail)
+                        } else {
+                            vec![]
+                        };
+                        if table_targets.is_empty() {
+                            unknown_jumps.insert(state.block);
+                        } else {
+                            resolved_jumps.insert(state.block);
+                        }
+                        table_targets.into_iter().filter_map(|target| self.block_idx_at(target.as_usize())).for_each(|next| {
+                            new_edges.push(Edge::new(block.id(), self.cfg[next].id(), EdgeKind::Jump, EdgeProvenance::JumpTable));
+                            successors.push((next, state.context.clone()));
+                        });
+                    }
+                    if let Some(next) = self.fallthrough_of(state.block).filter(|_| last_op.category() == OpType::JumpI) {
+                        new_edges.push(Edge::new(
+                            block.id(),
+                            self.cfg[next].id(),
+                            EdgeKind::JumpIFallthrough,
+                            EdgeProvenance::Symbolic,
+                        ));
+                        successors.push((next, state.context.clone()));
+                    }
+                },
+                Some(last_op) if last_op.halts() => {},
+                _ => {
+                    // Block was split at a JUMPDEST; execution runs straight into the next one.
+                    stack = block.exec_symbolic(stack, &self.code, block.ops.len());
+                    return_targets = block.exec_symbolic(return_targets, &self.code, block.ops.len());
+                    if let Some(next) = self.fallthrough_of(state.block) {
+                        successors.push((next, state.context.clone()));
+                    }
+                }
             }
 
+            for (next, context) in successors {
+                let edge = (state.block, next);
+                if state.trail.len() >= config.max_depth {
+                    report.limits_hit.insert(AnalysisLimit::MaxDepth);
+                    cut.entry(next).or_insert(AnalysisLimit::MaxDepth);
+                    continue;
+                }
+                if state.trail.iter().filter(|taken| **taken == edge).count() >= config.max_loop_unroll {
+                    report.limits_hit.insert(AnalysisLimit::LoopUnroll);
+                    cut.entry(next).or_insert(AnalysisLimit::LoopUnroll);
+                    continue;
+                }
+                let mut capture = stack.capture();
+                let mut targets = return_targets.capture();
+                let stack_hash = if config.context_sensitivity == ContextSensitivity::PathSensitive {
+                    let mut hasher = DefaultHasher::new();
+                    capture.vals.hash(&mut hasher);
+                    hasher.finish()
+                } else {
+                    0
+                };
+                let key = (next, context.clone(), stack_hash);
+                if let Some((prev, prev_targets)) = states.get(&key) {
+                    let joined = prev.widen(&capture);
+                    let joined_targets = prev_targets.widen(&targets);
+                    if joined.vals == prev.vals && joined_targets.vals == prev_targets.vals {
+                        continue;
+                    }
+                    capture = joined;
+                    targets = joined_targets;
+                }
+                states.insert(key, (capture.clone(), targets.clone()));
+                let mut trail = state.trail.clone();
+                trail.push(edge);
+                let frame = if context == state.context { state.frame } else { trail.len() };
+                queue.push_front(PathState {
+                    block: next,
+                    stack: capture,
+                    targets,
+                    context,
+                    trail,
+                    frame,
+                });
+            }
         }
 
-        return;
+        report.jumps_reached = reached_jumps.len();
+        report.jumps_resolved = reached_jumps.iter()
+            .filter(|idx| resolved_jumps.contains(idx) && !unknown_jumps.contains(idx))
+            .count();
+        self.edges.extend(new_edges);
+        self.invalid_jumps.extend(new_invalid_jumps);
+
+        self.block_nodes().for_each(|idx| {
+            let last_op = match self.cfg[idx].ops.last() {
+                Some(op) if matches!(op.category(), OpType::Jump | OpType::JumpI) => op,
+                _ => return,
+            };
+            if resolved_jumps.contains(&idx) && !unknown_jumps.contains(&idx) {
+                return;
+            }
+            let reason = if reached_jumps.contains(&idx) {
+                UnresolvedReason::UnknownTarget
+            } else if let Some(limit) = cut.get(&idx) {
+                UnresolvedReason::Limit(*limit)
+            } else if !report.limits_hit.is_empty() {
+                UnresolvedReason::Unexplored
+            } else {
+                return;
+            };
+            report.unresolved_jumps.insert(last_op.pc.unwrap(), reason);
+        });
 
+        self.report = report;
+        self.gen_fallthrough_edges();
+        &self.report
     }
 
+    /// Labels of the Vyper selector table whose lookup `block` ends, for a
+    /// jump to a label loaded from memory. The lookup runs from the block
+    /// copying the table's header to the jump: that one block for sparse
+    /// tables, a search loop over the bucket for dense ones. So the path is
+    /// walked back to the nearest header, unless it reaches a table label
+    /// first, which means the lookup already ended.
+    fn jump_table_targets(&self, block: NodeIndex, trail: &[(NodeIndex, NodeIndex)]) -> Vec<U256> {
+        for node in std::iter::once(block).chain(trail.iter().rev().map(|(from, _)| *from)) {
+            let id = self.cfg[node].id();
+            if let Some(table) = self.jump_tables.iter().find(|table| table.header_block == id) {
+                return table.targets.clone();
+            }
+            if self.jump_tables.iter().any(|table| table.targets.contains(&id)) {
+                break;
+            }
+        }
+        vec![]
+    }
+
+    /// Call context for the successor of an unconditional jump to `target`.
+    /// Jumping to the innermost return address leaves the current call; a jump
+    /// that leaves a JUMPDEST address pushed in the current frame on the stack
+    /// enters one. The Yul pipeline often pushes the return address well before
+    /// the block making the call, so `block` and every block on `frame`, the
+    /// edges taken since the context last changed, are searched. Constants
+    /// pushed before an earlier call or return are not, as a JUMPDEST left on
+    /// the stack from there is not this jump's return address.
+    fn call_context_after_jump(
+        &self,
+        block: &Block,
+        target: U256,
+        stack: &SymbolicStack,
+        context: &CallContext,
+        frame: &[(NodeIndex, NodeIndex)],
+        config: &AnalysisConfig,
+    ) -> CallContext {
+        let k = match config.context_sensitivity {
+            ContextSensitivity::PathSensitive => return context.clone(),
+            ContextSensitivity::CallString(k) => k,
+        };
+        if context.returns_to() == Some(target) {
+            return context.leave();
+        }
+        let pushers = std::iter::once(block).chain(frame.iter().rev().map(|(from, _)| &self.cfg[*from]));
+        match self.return_address(pushers, target, stack, context) {
+            Some(return_address) => context.enter(CallSite {
+                pc: block.ops.last().unwrap().pc.unwrap(),
+                return_address,
+            }, k),
+            None => context.clone(),
+        }
+    }
+
+    /// The address a JUMP to `target` leaves behind to come back to, if it looks
+    /// like an internal call: the last JUMPDEST pushed in `pushers`, given most
+    /// recent first, that is still on `stack` once the jump has executed and is
+    /// not already a return address of `context`.
+    pub(crate) fn return_address<'a>(
+        &self,
+        pushers: impl IntoIterator<Item = &'a Block>,
+        target: U256,
+        stack: &SymbolicStack,
+        context: &CallContext,
+    ) -> Option<U256> {
+        pushers.into_iter()
+            .flat_map(|block| block.ops.iter().rev().filter_map(|op| op.push_value(&self.code)))
+            .filter(|val| *val != target && *val < U256::from(self.code.len()) && self.code[val.as_usize()] == JUMPDEST)
+            .filter(|val| !context.returns_through(*val))
+            .find(|val| stack.holds(*val))
+    }
 
     pub fn gen_concrete_edges(&mut self) {
         let pattern_abs_jumps = vec![OpType::Push, OpType::Jump];
         let pattern_cond_jumps = vec![OpType::Push, OpType::JumpI];
-        let abs_jump_edges = self.blocks.iter().filter_map(|block| {
+        let abs_jump_edges = self.blocks().filter_map(|block| {
             let outgoing_op_seq = block.get_matching_op_sequences(&pattern_abs_jumps);
             if let Some(push_op_seq) = outgoing_op_seq.first() {
                 let push_op = &push_op_seq[0];
@@ -178,14 +560,14 @@ impl Program {
                 let dest = &self.code[start_read..end_read];
                 println!("Dest: {:?}", hex::encode(dest));
                 let dest = U256::from_big_endian(dest);
-                Some((block.id(), dest))
+                Some((push_op_seq[1].pc.unwrap(), Edge::new(block.id(), dest, EdgeKind::Jump, EdgeProvenance::PushJump)))
             } else {
                 None
             }
         }).collect::<Vec<_>>();
 
         let mut cond_jump_false_edges = vec![];
-        let cond_jump_true_edges = self.blocks.iter().filter_map(|block| {
+        let cond_jump_true_edges = self.blocks().filter_map(|block| {
             let outgoing_op_seq = block.get_matching_op_sequences(&pattern_cond_jumps);
             if let Some(push_op_seq) = outgoing_op_seq.first() {
                 let push_op = &push_op_seq[0];
@@ -195,8 +577,15 @@ impl Program {
                 let dest = &self.code[start_read..end_read];
                 println!("Dest: {:?}", hex::encode(dest));
                 let dest = U256::from_big_endian(dest);
-                cond_jump_false_edges.push((block.id(), U256::from(block.pc_end + 1)));
-                Some((block.id(), dest))
+                if block.pc_end + 1 < self.code.len() {
+                    cond_jump_false_edges.push(Edge::new(
+                        block.id(),
+                        U256::from(block.pc_end + 1),
+                        EdgeKind::JumpIFallthrough,
+                        EdgeProvenance::PushJump,
+                    ));
+                }
+                Some((push_op_seq[1].pc.unwrap(), Edge::new(block.id(), dest, EdgeKind::JumpITaken, EdgeProvenance::PushJump)))
 
 
             } else {
@@ -204,38 +593,367 @@ impl Program {
             }
         }).collect::<Vec<_>>();
 
-        self.edges.extend(abs_jump_edges.iter());
-        self.edges.extend(cond_jump_true_edges.iter());
-        self.edges.extend(cond_jump_false_edges.iter())
+        abs_jump_edges.into_iter().chain(cond_jump_true_edges).for_each(|(jump_pc, edge)| {
+            match self.invalid_target_reason(edge.to) {
+                Some(reason) => {
+                    self.invalid_jumps.insert(InvalidJump {
+                        pc: jump_pc,
+                        block: edge.from,
+                        target: edge.to,
+                        reason,
+                    });
+                },
+                None => {
+                    self.edges.insert(edge);
+                }
+            }
+        });
+        self.edges.extend(cond_jump_false_edges.iter());
 
-    }
+        // A sparse Vyper table is jumped through by the same block that copies it.
+        let table_edges = self.jump_tables.iter()
+            .filter(|table| self.block_at(table.header_block.as_usize())
+                .and_then(|block| block.ops.last())
+                .map_or(false, |op| op.category() == OpType::Jump))
+            .flat_map(|table| table.targets.iter().map(move |target| {
+                Edge::new(table.header_block, *target, EdgeKind::Jump, EdgeProvenance::JumpTable)
+            }))
+            .collect::<Vec<_>>();
+        self.edges.extend(table_edges);
+        self.gen_fallthrough_edges();
 
-    pub fn render(&self) -> Graph<BlockInfo, (u64, u64)> {
+    }
 
-        let mut id_to_idx = HashMap::new();
-        let mut graph_nodes = self.blocks.iter().map(|block| {
-            block.to_display_node()
+    /// Adds an edge into the next block for every block that can run off its
+    /// end: blocks split at a JUMPDEST and the not-taken side of every JUMPI.
+    pub fn gen_fallthrough_edges(&mut self) {
+        let fallthrough_edges = self.block_nodes().filter_map(|node| {
+            let (block, next) = (&self.cfg[node], &self.cfg[self.fallthrough_of(node)?]);
+            let last_op = block.ops.last()?;
+            let kind = match last_op.category() {
+                OpType::Jump => return None,
+                OpType::JumpI => EdgeKind::JumpIFallthrough,
+                _ if last_op.halts() => return None,
+                _ => EdgeKind::Fallthrough,
+            };
+            Some(Edge::new(block.id(), next.id(), kind, EdgeProvenance::PushJump))
         }).collect::<Vec<_>>();
-        graph_nodes.sort_by(|node1, node2| {
-            node1.code_loc.cmp(&node2.code_loc)
+        self.edges.extend(fallthrough_edges);
+        self.link_blocks();
+    }
+
+    /// Adds the block transitions seen in an execution trace, given as the
+    /// sequence of executed pcs (e.g. collected from an interpreter step hook).
+    pub fn add_trace_edges(&mut self, trace: &[usize]) {
+        let block_of = |pc: usize| self.block_map.get(pc).copied().flatten();
+        let mut trace_edges = vec![];
+        trace.windows(2).for_each(|step| {
+            // A step crosses an edge exactly when it lands on the start of a block.
+            let (from_block, to_block) = match (block_of(step[0]), block_of(step[1])) {
+                (Some(from), Some(to)) if step[1] == self.cfg[to].pc_start => (&self.cfg[from], &self.cfg[to]),
+                _ => return,
+            };
+            let kind = match from_block.ops.last().map(|op| op.category()) {
+                Some(OpType::Jump) => EdgeKind::Jump,
+                Some(OpType::JumpI) if to_block.pc_start == from_block.pc_end + 1 => EdgeKind::JumpIFallthrough,
+                Some(OpType::JumpI) => EdgeKind::JumpITaken,
+                _ => EdgeKind::Fallthrough,
+            };
+            trace_edges.push(Edge::new(from_block.id(), to_block.id(), kind, EdgeProvenance::Trace));
         });
-        let mut i = 0;
-        while i < graph_nodes.len() {
-            id_to_idx.insert(graph_nodes[i].code_loc, i as u64);
-            i += 1;
+        self.edges.extend(trace_edges);
+        self.link_blocks();
+    }
+
+    /// Rebuilds the edges of `cfg` from `edges`, `invalid_jumps` and the way
+    /// each block ends, then every block's `successors` and `predecessors`.
+    ///
+    /// Halting blocks get an edge to the exit or revert node, jumps with no
+    /// known target or listed in `report.unresolved_jumps` one to the
+    /// unresolved node, and the entry node one to pc 0.
+    pub fn link_blocks(&mut self) {
+        let mut links = vec![];
+        if let Some(first) = self.block_idx_at(0) {
+            links.push((self.synthetic.entry, first, Edge::new(0.into(), 0.into(), EdgeKind::Entry, EdgeProvenance::Synthetic)));
         }
-        let edges = self.edges.iter().map(|edge| {
-            let idx_for_id = id_to_idx.get(&edge.0.as_u64()).unwrap();
-            let idx_2_for_id = id_to_idx.get(&edge.1.as_u64()).unwrap();
-            (idx_for_id.clone() as u32, idx_2_for_id.clone() as u32)
-        }).collect::<Vec<_>>();
+        let mut resolved_jumps = HashSet::new();
+        self.edges.iter().for_each(|edge| {
+            if matches!(edge.kind, EdgeKind::Jump | EdgeKind::JumpITaken) {
+                resolved_jumps.insert(edge.from);
+            }
+            if let (Some(from), Some(to)) = (self.block_idx_at(edge.from.as_usize()), self.block_idx_at(edge.to.as_usize())) {
+                links.push((from, to, *edge));
+            }
+        });
+        self.invalid_jumps.iter().for_each(|jump| {
+            resolved_jumps.insert(jump.block);
+            if let Some(from) = self.block_idx_at(jump.block.as_usize()) {
+                let edge = Edge::new(jump.block, jump.target, EdgeKind::InvalidJump, EdgeProvenance::Synthetic);
+                links.push((from, self.synthetic.invalid_jump, edge));
+            }
+        });
+        self.block_nodes().for_each(|node| {
+            let block = &self.cfg[node];
+            let last_op = match block.ops.last() {
+                Some(op) => op,
+                None => return,
+            };
+            let last_pc = last_op.pc.unwrap();
+            let sink = match last_op.category() {
+                OpType::Jump | OpType::JumpI
+                    if !resolved_jumps.contains(&block.id()) || self.report.unresolved_jumps.contains_key(&last_pc) => {
+                    (self.synthetic.unresolved, EdgeKind::Unresolved)
+                },
+                OpType::Jump | OpType::JumpI => return,
+                _ if last_op.halts() && (last_op.is_invalid || [REVERT, INVALID].contains(&last_op.code.u8())) => {
+                    (self.synthetic.revert, EdgeKind::Halt)
+                },
+                _ if last_op.halts() => (self.synthetic.exit, EdgeKind::Halt),
+                // Running off the end of the code is an implicit STOP.
+                _ if self.fallthrough_of(node).is_none() => (self.synthetic.exit, EdgeKind::Halt),
+                _ => return,
+            };
+            links.push((node, sink.0, Edge::new(block.id(), last_pc, sink.1, EdgeProvenance::Synthetic)));
+        });
 
-        let mut g = DiGraph::from_elements(graph_nodes.iter().map(|n| {
-            Element::Node { weight: n.clone() }
+        self.analyses = Analyses::default();
+        self.cfg.clear_edges();
+        links.into_iter().for_each(|(from, to, edge)| {
+            self.cfg.add_edge(from, to, edge);
+        });
+        let nodes = self.cfg.node_indices().collect::<Vec<_>>();
+        nodes.into_iter().for_each(|node| {
+            let neighbor_pcs = |direction| {
+                self.cfg.neighbors_directed(node, direction)
+                    .filter(|next| self.cfg[*next].kind == BlockKind::Code)
+                    .map(|next| self.cfg[next].pc_start)
+                    .collect::<BTreeSet<_>>()
+                    .into_iter()
+                    .collect::<Vec<_>>()
+            };
+            let successors = neighbor_pcs(Direction::Outgoing);
+            let predecessors = neighbor_pcs(Direction::Incoming);
+            self.cfg[node].successors = successors;
+            self.cfg[node].predecessors = predecessors;
+        });
+    }
 
-        }));
-        g.extend_with_edges(edges);
-        g
+    /// Dominator and post-dominator trees of the current CFG.
+    pub fn dominance(&self) -> &Dominance {
+        self.analyses.dominance.get_or_init(|| Dominance::new(self))
+    }
+
+    /// Natural loops of the current CFG and where their exit conditions come from.
+    pub fn loop_info(&self) -> &LoopInfo {
+        self.analyses.loop_info.get_or_init(|| LoopInfo::new(self))
+    }
+
+    /// Internal functions recovered from call and return jumps, and their call graph.
+    pub fn functions(&self) -> &Functions {
+        self.analyses.functions.get_or_init(|| Functions::new(self))
+    }
+
+    /// External functions by selector, plus the fallback and receive entries.
+    pub fn dispatcher(&self) -> &Dispatcher {
+        self.analyses.dispatcher.get_or_init(|| Dispatcher::new(self))
+    }
+
+    /// What every byte of `code` is, and how much of the code is reachable.
+    pub fn coverage(&self) -> &Coverage {
+        self.analyses.coverage.get_or_init(|| Coverage::new(self))
+    }
+
+    /// Contracts this one deploys with CREATE/CREATE2 from initcode embedded in its code.
+    pub fn children(&self) -> &[ChildContract] {
+        self.analyses.children.get_or_init(|| child_contracts(self))
+    }
+
+    /// The code blocks lifted to stack-free SSA; print it for the IR listing.
+    pub fn ssa(&self) -> &SsaProgram {
+        self.analyses.ssa.get_or_init(|| SsaProgram::lift(self))
+    }
+
+    /// Sources of attacker-controlled data that reach a jump target, storage
+    /// slot, call target or value, SELFDESTRUCT beneficiary or CREATE2 salt.
+    pub fn taint_flows(&self) -> &[TaintFlow] {
+        self.analyses.taint_flows.get_or_init(|| taint_flows(self))
+    }
+
+    /// SSTOREs reachable after an external call that forwards more than the 2300 gas stipend.
+    pub fn reentrancy(&self) -> &[Reentrancy] {
+        self.analyses.reentrancy.get_or_init(|| reentrancy(self))
+    }
+
+    /// SELFDESTRUCT, DELEGATECALL and EIP-1967 slot writes per external function,
+    /// with the CALLER check dominating each one, if any.
+    pub fn access_control(&self) -> &[AccessControl] {
+        self.analyses.access_control.get_or_init(|| access_control(self))
+    }
+
+    /// Jump targets and storage slots calldata controls: arbitrary jumps and writes.
+    pub fn controlled_sites(&self) -> &[ControlledSite] {
+        self.analyses.controlled_sites.get_or_init(|| controlled_sites(self))
+    }
+
+    /// External calls, including `send`, whose success flag decides no JUMPI with an aborting branch.
+    pub fn unchecked_calls(&self) -> &[UncheckedCall] {
+        self.analyses.unchecked_calls.get_or_init(|| unchecked_calls(self))
+    }
+
+    /// Branches, call values and storage writes decided by tx.origin or block values.
+    pub fn environment_dependence(&self) -> &[EnvironmentDependence] {
+        self.analyses.environment_dependence.get_or_init(|| environment_dependence(self))
+    }
+
+    /// Findings of the detectors `registry` has enabled, ordered by pc.
+    pub fn detect(&self, registry: &DetectorRegistry) -> Vec<Finding> {
+        registry.run(self)
+    }
+
+    /// Runs a data-flow analysis to a fixpoint over the current CFG.
+    pub fn solve<A: DataFlowAnalysis>(&self, analysis: &A) -> DataFlowResults<A::Fact> {
+        dataflow::solve(analysis, self)
+    }
+
+    /// Whether every path from entry to block `b` goes through block `a`.
+    pub fn dominates(&self, a: NodeIndex, b: NodeIndex) -> bool {
+        self.dominance().dominates(a, b)
+    }
+
+    /// Whether every path from block `b` to a halting node goes through block `a`.
+    pub fn post_dominates(&self, a: NodeIndex, b: NodeIndex) -> bool {
+        self.dominance().post_dominates(a, b)
+    }
+
+    /// Whether the instruction at pc `a` executes on every path to the one at pc `b`.
+    pub fn pc_dominates(&self, a: usize, b: usize) -> bool {
+        let (block_a, block_b) = match (self.node_containing(a), self.node_containing(b)) {
+            (Some(block_a), Some(block_b)) => (block_a, block_b),
+            _ => return false,
+        };
+        if block_a == block_b {
+            a <= b && self.dominance().dominates(block_a, block_b)
+        } else {
+            self.dominates(block_a, block_b)
+        }
+    }
+
+    /// Code blocks in pc order.
+    pub fn blocks(&self) -> impl Iterator<Item = &Block> {
+        self.cfg.node_weights().filter(|block| block.kind == BlockKind::Code)
+    }
+
+    /// Nodes of the code blocks in pc order.
+    pub fn block_nodes(&self) -> impl Iterator<Item = NodeIndex> + '_ {
+        self.cfg.node_indices().filter(move |node| self.cfg[*node].kind == BlockKind::Code)
+    }
+
+    /// The block starting exactly at `pc`.
+    pub fn block_at(&self, pc: usize) -> Option<&Block> {
+        self.block_idx_at(pc).map(|idx| &self.cfg[idx])
+    }
+
+    /// The block whose instructions, including PUSH immediates, cover `pc`.
+    pub fn block_containing(&self, pc: usize) -> Option<&Block> {
+        self.node_containing(pc).map(|idx| &self.cfg[idx])
+    }
+
+    /// Node of the block whose instructions, including PUSH immediates, cover `pc`.
+    pub fn node_containing(&self, pc: usize) -> Option<NodeIndex> {
+        self.block_map.get(pc).copied().flatten()
+    }
+
+    /// Node of the block starting exactly at `pc`.
+    pub fn block_idx_at(&self, pc: usize) -> Option<NodeIndex> {
+        self.block_map.get(pc).copied().flatten().filter(|idx| self.cfg[*idx].pc_start == pc)
+    }
+
+    /// The synthetic node standing in for `kind`; `None` for `BlockKind::Code`.
+    pub fn synthetic_node(&self, kind: BlockKind) -> Option<NodeIndex> {
+        match kind {
+            BlockKind::Code => None,
+            BlockKind::Entry => Some(self.synthetic.entry),
+            BlockKind::Exit => Some(self.synthetic.exit),
+            BlockKind::Revert => Some(self.synthetic.revert),
+            BlockKind::Unresolved => Some(self.synthetic.unresolved),
+            BlockKind::InvalidJump => Some(self.synthetic.invalid_jump),
+        }
+    }
+
+    /// The block that starts right after the last instruction of `node`.
+    pub fn fallthrough_of(&self, node: NodeIndex) -> Option<NodeIndex> {
+        let last_op = self.cfg[node].ops.last()?;
+        self.block_idx_at(last_op.pc?.as_usize() + last_op.arg_size as usize + 1)
+    }
+
+    /// `invalid_jump_reason` for this code, with JUMPDEST bytes in the
+    /// metadata trailer, which have no block, reported as `IntoMetadata`.
+    fn invalid_target_reason(&self, target: U256) -> Option<InvalidJumpReason> {
+        invalid_jump_reason(&self.code, &self.jumpdests, target).or_else(|| self.metadata.as_ref()
+            .filter(|metadata| metadata.contains(&target.as_usize()))
+            .map(|_| InvalidJumpReason::IntoMetadata))
+    }
+
+    /// The CFG with each block rendered as its opcode listing. Synthetic nodes
+    /// without edges are left out. No analysis is run; see
+    /// `render_with_findings` to annotate blocks.
+    pub fn render(&self) -> Graph<BlockInfo, (u64, u64)> {
+        self.render_with_findings(&[], &[])
+    }
+
+    /// `render()`, with `children` noted on the blocks creating them and the
+    /// blocks holding `sites` listed and highlighted. Pass
+    /// `self.children()` and `self.controlled_sites()` to annotate everything.
+    pub fn render_with_findings(&self, children: &[ChildContract], sites: &[ControlledSite]) -> Graph<BlockInfo, (u64, u64)> {
+        let rendered = self.cfg.filter_map(
+            |node, block| {
+                let connected = self.cfg.neighbors_undirected(node).next().is_some();
+                (block.kind == BlockKind::Code || connected).then(|| {
+                    let mut info = block.to_display_node();
+                    children.iter().enumerate()
+                        .filter(|(_, child)| self.node_containing(child.create_pc.as_usize()) == Some(node))
+                        .for_each(|(idx, child)| {
+                            info.ops += &format!("\n-> child {} (initcode {:#x}..{:#x})", idx, child.initcode.start, child.initcode.end);
+                        });
+                    sites.iter()
+                        .filter(|site| self.node_containing(site.pc.as_usize()) == Some(node))
+                        .for_each(|site| {
+                            let what = match site.kind {
+                                ControlledSiteKind::JumpTarget => "arbitrary jump",
+                                ControlledSiteKind::StorageSlot => "arbitrary storage write",
+                            };
+                            let offsets = site.calldata_offsets.iter().map(|offset| format!("{:#x}", offset)).collect::<Vec<_>>();
+                            info.ops += &format!("\n!! {} at {:#x} (calldata [{}]{})", what, site.pc, offsets.join(", "),
+                                                 if site.masked { ", masked" } else { "" });
+                            info.highlight = true;
+                        });
+                    info
+                })
+            },
+            |_, edge| Some((edge.from.as_u64(), edge.to.as_u64())),
+        );
+        Graph::from(rendered)
+    }
+
+    /// `render()` in Graphviz format.
+    pub fn render_dot(&self) -> String {
+        Self::dot(&self.render())
+    }
+
+    /// `render_with_findings()` in Graphviz format, with highlighted blocks
+    /// filled red.
+    pub fn render_dot_with_findings(&self, children: &[ChildContract], sites: &[ControlledSite]) -> String {
+        Self::dot(&self.render_with_findings(children, sites))
+    }
+
+    fn dot(graph: &Graph<BlockInfo, (u64, u64)>) -> String {
+        let dot = Dot::with_attr_getters(
+            graph,
+            &[Config::EdgeNoLabel],
+            &|_, _| String::new(),
+            &|_, (_, info)| if info.highlight { "style=filled fillcolor=red".to_string() } else { String::new() },
+        );
+        format!("{:?}", dot)
     }
 }
 
@@ -250,22 +968,64 @@ pub struct Block {
     pub pc_start: usize,
     pub pc_end: usize,
     pub ops: Vec<Operation>,
-    pub successors: Vec<usize>
- 
+    /// Starts of the blocks this block has an edge to, ascending.
+    pub successors: Vec<usize>,
+    /// Starts of the blocks with an edge to this block, ascending.
+    pub predecessors: Vec<usize>,
+    pub kind: BlockKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BlockKind {
+    /// Instructions from the bytecode.
+    Code,
+    /// Where execution starts; its only edge goes to the block at pc 0.
+    Entry,
+    /// Successful termination: STOP, RETURN, SELFDESTRUCT or running off the end.
+    Exit,
+    /// Failed termination: REVERT, INVALID or an undefined opcode.
+    Revert,
+    /// Destination of every jump whose target is not known.
+    Unresolved,
+    /// Destination of every jump to something other than a JUMPDEST.
+    InvalidJump,
 }
 
 #[derive(Debug, Clone, Default)]
 pub struct CfgNodeData {
     pub ops: String,
-    pub code_loc: u64
+    /// Start of the block; `u64::MAX` for synthetic nodes, which have no pc.
+    pub code_loc: u64,
+    /// Set by `Program::render_with_findings` on blocks holding a finding worth a look.
+    pub highlight: bool,
 }
 
 impl Block {
+    /// A node with no instructions standing in for `kind`. It has no pc: its
+    /// `pc_start` and `pc_end` are `usize::MAX` and `pc()` is `None`, and the
+    /// node is found through `Program::synthetic` or `Program::synthetic_node`.
+    pub fn synthetic(kind: BlockKind) -> Self {
+        Block {
+            pc_start: usize::MAX,
+            pc_end: usize::MAX,
+            ops: vec![],
+            successors: vec![],
+            predecessors: vec![],
+            kind,
+        }
+    }
+
+    /// The pc of a code block's first instruction; only meaningful for code blocks.
     pub fn id(&self) -> U256 {
         self.pc_start.into()
     }
 
-    pub fn exec_symbolic(&mut self, mut stack: SymbolicStack, code: &[u8], num_codes: usize) -> SymbolicStack {
+    /// Where the block starts, or `None` for a synthetic node.
+    pub fn pc(&self) -> Option<usize> {
+        (self.kind == BlockKind::Code).then(|| self.pc_start)
+    }
+
+    pub fn exec_symbolic<V: AbstractValue>(&self, mut stack: SymbolicStack<V>, code: &[u8], num_codes: usize) -> SymbolicStack<V> {
         (0..num_codes).into_iter().for_each(|code_idx| {
             let op = &self.ops[code_idx];
             stack.execute(op, code);
@@ -276,13 +1036,20 @@ impl Block {
     }
 
     pub fn to_display_node(&self) -> CfgNodeData {
-        let id = self.id();
-        let ops = self.ops.iter().map(|op| {
-            op.code.as_str().to_string()
-        }).collect::<Vec<_>>().join(" ");
+        let ops = match self.kind {
+            BlockKind::Code => self.ops.iter().map(|op| {
+                op.code.as_str().to_string()
+            }).collect::<Vec<_>>().join(" "),
+            BlockKind::Entry => "ENTRY".to_string(),
+            BlockKind::Exit => "EXIT".to_string(),
+            BlockKind::Revert => "REVERT".to_string(),
+            BlockKind::Unresolved => "UNRESOLVED JUMP".to_string(),
+            BlockKind::InvalidJump => "INVALID JUMP".to_string(),
+        };
         CfgNodeData {
-            code_loc: id.as_u64(),
-            ops
+            code_loc: self.pc().map_or(u64::MAX, |pc| pc as u64),
+            ops,
+            highlight: false,
         }
 
     }
@@ -341,12 +1108,12 @@ mod tests {
         println!("EDGES: {:?}", pgm.edges);
         //
         let edges_from_orphan = pgm.edges.iter().find(|edge| {
-            edge.0.as_usize() == 230
+            edge.from.as_usize() == 230
         });
         assert!(edges_from_orphan.is_none());
         pgm.gen_symbolic_edges();
         let edges_from_orphan = pgm.edges.iter().find(|edge| {
-            edge.0.as_usize() == 230
+            edge.from.as_usize() == 230
         });
          assert!(edges_from_orphan.is_some());
         let g = pgm.render();
@@ -355,6 +1122,800 @@ mod tests {
         assert!(false);
     }
 
+    fn has_edge(pgm: &Program, from: u64, to: u64) -> bool {
+        pgm.edges.iter().any(|edge| edge.from == U256::from(from) && edge.to == U256::from(to))
+    }
+
+    // PUSH1 0x04 JUMP STOP | JUMPDEST PUSH1 0x09 JUMP STOP | JUMPDEST STOP
+    const TWO_JUMPS: &str = "600456005b600956005b00";
+
+    #[test]
+    fn symbolic_edges_resolve_push_jump() {
+        let mut pgm = Program::parse_bytecode(hex::decode(TWO_JUMPS).unwrap(), None);
+        let report = pgm.gen_symbolic_edges_with_config(&AnalysisConfig::default()).clone();
+        assert!(report.is_complete());
+        assert!(report.unresolved_jumps.is_empty());
+        assert!(has_edge(&pgm, 0, 4));
+        assert!(has_edge(&pgm, 4, 9));
+    }
+
+    #[test]
+    fn symbolic_edges_report_state_limit() {
+        let mut pgm = Program::parse_bytecode(hex::decode(TWO_JUMPS).unwrap(), None);
+        let report = pgm.gen_symbolic_edges_with_config(&AnalysisConfig::default().max_states(1)).clone();
+        assert!(report.limits_hit.contains(&AnalysisLimit::MaxStates));
+        assert_eq!(
+            report.unresolved_jumps.get(&U256::from(7)),
+            Some(&UnresolvedReason::Limit(AnalysisLimit::MaxStates))
+        );
+        assert!(!has_edge(&pgm, 4, 9));
+    }
+
+    #[test]
+    fn edges_are_deduplicated_across_passes() {
+        let mut pgm = Program::parse_bytecode(hex::decode(TWO_JUMPS).unwrap(), None);
+        pgm.gen_concrete_edges();
+        pgm.gen_symbolic_edges();
+        pgm.add_trace_edges(&[0, 2, 4, 5, 7, 9, 10]);
+        assert_eq!(pgm.edges.iter().filter(|edge| edge.from == U256::from(0)).count(), 1);
+        let first = pgm.edges.iter().find(|edge| edge.from == U256::from(0)).unwrap();
+        assert_eq!(first.kind, EdgeKind::Jump);
+        assert_eq!(first.provenance, EdgeProvenance::PushJump);
+        assert!(pgm.edges.contains(&Edge::new(9.into(), 10.into(), EdgeKind::Fallthrough, EdgeProvenance::Trace)));
+    }
+
+    #[test]
+    fn blocks_split_at_jumpdest_fall_through() {
+        // PUSH1 0x01 | JUMPDEST PUSH1 0x00 JUMPI | STOP ...
+        let mut pgm = Program::parse_bytecode(hex::decode("60015b600057000000").unwrap(), None);
+        pgm.gen_concrete_edges();
+        assert!(pgm.edges.contains(&Edge::new(0.into(), 2.into(), EdgeKind::Fallthrough, EdgeProvenance::PushJump)));
+        assert!(pgm.edges.contains(&Edge::new(2.into(), 6.into(), EdgeKind::JumpIFallthrough, EdgeProvenance::PushJump)));
+        assert!(!pgm.edges.iter().any(|edge| edge.from == U256::from(6)));
+    }
+
+    #[test]
+    fn jumps_into_push_data_are_reported() {
+        // PUSH1 0x04 JUMP | PUSH1 0x5b STOP | PUSH1 0x01 JUMPI
+        let mut pgm = Program::parse_bytecode(hex::decode("600456605b00600157000000").unwrap(), None);
+        assert_eq!(pgm.jumpdests.iter().filter(|valid| **valid).count(), 0);
+        pgm.gen_concrete_edges();
+        pgm.gen_symbolic_edges();
+        let reasons = pgm.invalid_jumps.iter().map(|jump| (jump.pc.as_u64(), jump.reason)).collect::<Vec<_>>();
+        assert_eq!(reasons, vec![(2, InvalidJumpReason::InsidePushData), (8, InvalidJumpReason::NotJumpdest)]);
+        assert!(!pgm.edges.iter().any(|edge| edge.kind == EdgeKind::Jump));
+        let g = pgm.render();
+        assert!(g.node_weights().any(|node| node.ops == "INVALID JUMP"));
+    }
+
+    #[test]
+    fn jumps_into_metadata_are_reported() {
+        // PUSH1 0x0b JUMP | STOP | a1 64 "solc" 43 5b0811 000a
+        // The solc version bytes hold a 0x5b the interpreter counts as a JUMPDEST.
+        let mut pgm = Program::parse_bytecode(hex::decode("600b5600a164736f6c63435b0811000a").unwrap(), None);
+        assert_eq!(pgm.metadata, Some(4..16));
+        assert!(pgm.jumpdests[0x0b]);
+        pgm.gen_concrete_edges();
+        pgm.gen_symbolic_edges();
+        let reasons = pgm.invalid_jumps.iter().map(|jump| (jump.pc.as_u64(), jump.reason)).collect::<Vec<_>>();
+        assert_eq!(reasons, vec![(2, InvalidJumpReason::IntoMetadata)]);
+        assert!(pgm.report.unresolved_jumps.is_empty());
+        assert_eq!(pgm.report.resolution_rate(), 1.0);
+    }
+
+    #[test]
+    fn blocks_are_linked_and_indexed() {
+        let mut pgm = Program::parse_bytecode(hex::decode(TWO_JUMPS).unwrap(), None);
+        pgm.gen_concrete_edges();
+        assert_eq!(pgm.block_containing(6).unwrap().pc_start, 4);
+        assert!(pgm.block_at(6).is_none());
+        let block = pgm.block_at(4).unwrap();
+        assert_eq!(block.predecessors, vec![0]);
+        assert_eq!(block.successors, vec![9]);
+        assert_eq!(pgm.block_at(9).unwrap().successors, vec![10]);
+    }
+
+    #[test]
+    fn partly_resolved_jumps_keep_their_unresolved_edge() {
+        // The jump at 0x11 returns to 0x12 when reached from 0x05, but to a
+        // calldata value when reached from 0x09.
+        let mut pgm = Program::parse_bytecode(hex::decode("3660095760126010565b6000356010565b565b0000").unwrap(), None);
+        pgm.gen_symbolic_edges();
+        assert_eq!(pgm.report.unresolved_jumps.get(&U256::from(0x11)), Some(&UnresolvedReason::UnknownTarget));
+        assert_eq!((pgm.report.jumps_reached, pgm.report.jumps_resolved), (4, 3));
+        assert_eq!(pgm.report.resolution_rate(), 0.75);
+        let jump = pgm.block_idx_at(0x10).unwrap();
+        assert!(pgm.cfg.contains_edge(jump, pgm.block_idx_at(0x12).unwrap()));
+        assert!(pgm.cfg.contains_edge(jump, pgm.synthetic.unresolved));
+    }
+
+    #[test]
+    fn cfg_has_synthetic_entry_and_sinks() {
+        // PUSH1 0x04 JUMPI | REVERT | JUMPDEST CALLDATALOAD JUMP
+        let mut pgm = Program::parse_bytecode(hex::decode("600457fd5b3556").unwrap(), None);
+        pgm.gen_symbolic_edges();
+        let nodes = pgm.synthetic;
+        let edges_into = |node| pgm.cfg.edges_directed(node, Direction::Incoming).count();
+        assert_eq!(pgm.cfg.neighbors(nodes.entry).collect::<Vec<_>>(), vec![pgm.block_idx_at(0).unwrap()]);
+        assert_eq!(edges_into(nodes.revert), 1);
+        assert_eq!(edges_into(nodes.unresolved), 1);
+        assert_eq!(edges_into(nodes.exit), 0);
+        assert!(petgraph::algo::has_path_connecting(&pgm.cfg, nodes.entry, nodes.unresolved, None));
+        assert_eq!(pgm.block_at(4).unwrap().predecessors, vec![0]);
+        // Synthetic nodes have no pc and are found by kind.
+        assert_eq!(pgm.synthetic_node(BlockKind::Revert), Some(nodes.revert));
+        assert!([nodes.entry, nodes.exit, nodes.revert, nodes.unresolved, nodes.invalid_jump].iter().all(|node| pgm.cfg[*node].pc().is_none()));
+        assert_eq!(pgm.block_at(pgm.code.len()).map(|block| block.kind), None);
+        assert!(pgm.cfg.edges_directed(nodes.revert, Direction::Incoming).all(|edge| edge.weight().provenance == EdgeProvenance::Synthetic));
+    }
+
+    #[test]
+    fn dominators_follow_branches() {
+        // PUSH1 0x07 JUMPI | PUSH1 0x08 JUMP | JUMPDEST | JUMPDEST STOP
+        let mut pgm = Program::parse_bytecode(hex::decode("600757600856005b5b0000").unwrap(), None);
+        pgm.gen_symbolic_edges();
+        let node = |pc| pgm.block_idx_at(pc).unwrap();
+        assert!(pgm.dominates(node(0), node(8)));
+        assert!(!pgm.dominates(node(3), node(8)));
+        assert!(!pgm.dominates(node(7), node(8)));
+        assert!(pgm.post_dominates(node(8), node(0)));
+        assert!(!pgm.post_dominates(node(3), node(0)));
+        assert_eq!(pgm.dominance().frontier(node(3)).collect::<Vec<_>>(), vec![node(8)]);
+        assert!(pgm.pc_dominates(1, 8));
+        assert!(!pgm.pc_dominates(2, 1));
+    }
+
+    #[test]
+    fn loops_are_found_with_their_bounds() {
+        // PUSH1 0 | JUMPDEST PUSH1 0 SLOAD DUP2 LT ISZERO PUSH1 0x12 JUMPI
+        // | PUSH1 1 ADD PUSH1 2 JUMP | JUMPDEST STOP
+        let mut pgm = Program::parse_bytecode(hex::decode("60005b6000548110156012576001016002565b000000").unwrap(), None);
+        pgm.gen_symbolic_edges();
+        let node = |pc| pgm.block_idx_at(pc).unwrap();
+        let info = pgm.loop_info();
+        assert_eq!(info.loops.len(), 1);
+        let lp = &info.loops[0];
+        assert_eq!(lp.header, node(2));
+        assert_eq!(lp.body, BTreeSet::from([node(2), node(12)]));
+        assert_eq!(lp.back_edges, vec![(node(12), node(2))]);
+        assert_eq!(lp.exits, vec![(node(2), node(18))]);
+        assert_eq!(lp.bound, BTreeSet::from([BoundSource::Storage]));
+        assert_eq!(info.depth(node(12)), 1);
+        assert_eq!(info.depth(node(18)), 0);
+    }
+
+    // An internal function at 0x14 called from two sites, returning to 0x08 and 0x0f.
+    const SHARED_RETURN: &str = "60086014560000005b600f601456005b000000005b5600000000000000000000";
+
+    #[test]
+    fn call_strings_keep_returns_apart() {
+        let ret = |pgm: &Program, to: u64| has_edge(pgm, 0x14, to);
+
+        // Merging both callers under one context must not lose either return.
+        [ContextSensitivity::PathSensitive, ContextSensitivity::CallString(0), ContextSensitivity::CallString(1), ContextSensitivity::CallString(2)]
+            .into_iter()
+            .for_each(|sensitivity| {
+                let mut pgm = Program::parse_bytecode(hex::decode(SHARED_RETURN).unwrap(), None);
+                let report = pgm.gen_symbolic_edges_with_config(&AnalysisConfig::default().context_sensitivity(sensitivity));
+                assert!(report.unresolved_jumps.is_empty(), "{:?}", sensitivity);
+                assert!(ret(&pgm, 0x08) && ret(&pgm, 0x0f), "{:?}", sensitivity);
+            });
+    }
+
+    #[test]
+    fn internal_functions_are_recovered() {
+        let mut pgm = Program::parse_bytecode(hex::decode(SHARED_RETURN).unwrap(), None);
+        pgm.gen_symbolic_edges();
+        let node = |pc| pgm.block_idx_at(pc).unwrap();
+        let functions = pgm.functions();
+        let callee = &functions.functions[&U256::from(0x14)];
+        assert_eq!(callee.blocks, BTreeSet::from([node(0x14)]));
+        assert_eq!(callee.exits, BTreeSet::from([node(0x14)]));
+        assert_eq!(callee.call_sites.iter().map(|call| call.return_address.as_usize()).collect::<Vec<_>>(), vec![0x08, 0x0f]);
+        assert_eq!(callee.callers, BTreeSet::from([U256::zero()]));
+        assert_eq!(callee.signature, Some(StackSignature { args: 0, returns: 0 }));
+        let root = &functions.functions[&U256::zero()];
+        assert_eq!(root.blocks, BTreeSet::from([node(0), node(0x08), node(0x0f)]));
+        assert!(functions.call_graph.contains_edge(U256::zero(), U256::from(0x14)));
+    }
+
+    // Non-payable contract with two selectors split on GT, as solc lays it out.
+    const DISPATCHER: &str = "6080604052348015600f57600080fd5b506004361060405760003560e01c8063aabbccdd11603557806311111111146045576040565b8063aabbccdd146047575b600080fd5b005b000000";
+
+    #[test]
+    fn dispatcher_maps_selectors_to_entries() {
+        let mut pgm = Program::parse_bytecode(hex::decode(DISPATCHER).unwrap(), None);
+        pgm.gen_symbolic_edges();
+        let dispatcher = pgm.dispatcher();
+        let entries = dispatcher.functions.iter()
+            .map(|function| (function.selector, function.entry.as_usize(), function.payable))
+            .collect::<Vec<_>>();
+        assert_eq!(entries, vec![([0x11, 0x11, 0x11, 0x11], 0x45, false), ([0xaa, 0xbb, 0xcc, 0xdd], 0x47, false)]);
+        assert_eq!(dispatcher.entry_of([0xaa, 0xbb, 0xcc, 0xdd]), Some(U256::from(0x47)));
+        assert_eq!(dispatcher.fallback, None);
+        assert_eq!(dispatcher.receive, None);
+        let entry = pgm.block_idx_at(0x45).unwrap();
+        assert_eq!(dispatcher.functions_containing(entry).count(), 1);
+    }
+
+    #[test]
+    fn fallback_is_where_the_last_comparison_goes() {
+        // Short calldata jumps to 0x1a; the last comparison falls through to a
+        // trampoline into the fallback at 0x1c, which sorts after it.
+        let code = concat!(
+            "60043610601a57", "60003560e01c8063aabbccdd14602257", "601c56",
+            "5b00", "5b3360005500", "5b00", "00",
+        );
+        let mut pgm = Program::parse_bytecode(hex::decode(code).unwrap(), None);
+        pgm.gen_symbolic_edges();
+        let dispatcher = pgm.dispatcher();
+        assert_eq!(dispatcher.entry_of([0xaa, 0xbb, 0xcc, 0xdd]), Some(U256::from(0x22)));
+        assert_eq!(dispatcher.fallback, Some(U256::from(0x1c)));
+        assert_eq!(dispatcher.receive, None);
+    }
+
+    #[test]
+    fn fallback_is_found_behind_a_trampoline_chain() {
+        // As above, but the last comparison reaches the fallback at 0x2d
+        // through five trampolines, one of them a lone JUMPDEST at 0x22.
+        let code = concat!(
+            "60043610602b57", "60003560e01c8063aabbccdd14603357", "601a56",
+            "5b601e56", "5b602256", "5b", "5b602756", "5b602d56",
+            "5b00", "5b3360005500", "5b00", "00",
+        );
+        let mut pgm = Program::parse_bytecode(hex::decode(code).unwrap(), None);
+        pgm.gen_symbolic_edges();
+        assert_eq!(pgm.dispatcher().fallback, Some(U256::from(0x2d)));
+    }
+
+    // Vyper-style sparse table: selector % 2 picks a bucket label at 0x40.
+    const VYPER_SPARSE: &str = "60003560e01c6002810660011b61004001600290601e39600051565b63123456788114603c576037565b63abcdef018114603e576037565b600080fd5b005b00001b002900";
+
+    #[test]
+    fn vyper_sparse_jump_table_is_followed() {
+        let mut pgm = Program::parse_bytecode(hex::decode(VYPER_SPARSE).unwrap(), None);
+        assert_eq!(pgm.jump_tables.len(), 1);
+        let table = &pgm.jump_tables[0];
+        assert_eq!(table.kind, JumpTableKind::Sparse);
+        assert_eq!(table.data, 0x40..0x44);
+        assert_eq!(table.targets, vec![U256::from(0x1b), U256::from(0x29)]);
+
+        pgm.gen_symbolic_edges();
+        assert!(has_edge(&pgm, 0, 0x1b) && has_edge(&pgm, 0, 0x29));
+        assert!(pgm.report.unresolved_jumps.is_empty());
+        let entries = pgm.dispatcher().functions.iter()
+            .map(|function| (function.selector, function.entry.as_usize()))
+            .collect::<Vec<_>>();
+        assert_eq!(entries, vec![([0x12, 0x34, 0x56, 0x78], 0x3c), ([0xab, 0xcd, 0xef, 0x01], 0x3e)]);
+        assert_eq!(pgm.dispatcher().fallback, None);
+    }
+
+    // Vyper-style dense table with one bucket at 0x62; the entries at 0x67 are
+    // searched in a loop before jumping to the matching label.
+    const VYPER_DENSE: &str = concat!(
+        "60003560e01c6001810660050261006201600590601b396000518060081c61ffff169060ff166000",
+        "5b818110156059576007810283016007906019396000",
+        "5160181c8414604d576001016028565b60005160081c61ffff16565b600080fd5b005b00",
+        "00000067021234567800", "5e00abcdef0100600000",
+    );
+
+    #[test]
+    fn vyper_dense_jump_table_is_followed() {
+        let mut pgm = Program::parse_bytecode(hex::decode(VYPER_DENSE).unwrap(), None);
+        assert_eq!(pgm.jump_tables.len(), 1);
+        let table = &pgm.jump_tables[0];
+        assert_eq!(table.kind, JumpTableKind::Dense);
+        assert_eq!(table.data, 0x62..0x75);
+        assert_eq!(table.targets, vec![U256::from(0x5e), U256::from(0x60)]);
+
+        // The second entry is only found after going round the loop, more
+        // blocks after the header than a fixed lookbehind would cover.
+        pgm.gen_symbolic_edges();
+        assert!(has_edge(&pgm, 0x4d, 0x5e) && has_edge(&pgm, 0x4d, 0x60));
+        assert!(pgm.report.unresolved_jumps.is_empty(), "{:?}", pgm.report.unresolved_jumps);
+        let entries = pgm.dispatcher().functions.iter()
+            .map(|function| (function.selector, function.entry.as_usize()))
+            .collect::<Vec<_>>();
+        assert_eq!(entries, vec![([0x12, 0x34, 0x56, 0x78], 0x5e), ([0xab, 0xcd, 0xef, 0x01], 0x60)]);
+    }
+
+    #[test]
+    fn via_ir_snippets_resolve() {
+        // test-data/via_ir holds hand-assembled snippets of the Yul pipeline's
+        // call idioms, not `solc --via-ir` output, and there are no legacy
+        // builds of the same sources to compare resolution rates with. Each
+        // snippet must resolve every jump it reaches.
+        let data = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test-data");
+        let analyze = |path: PathBuf| {
+            let code = std::fs::read_to_string(path).unwrap();
+            let mut pgm = Program::parse_bytecode(hex::decode(code.trim()).unwrap(), None);
+            pgm.gen_symbolic_edges();
+            pgm
+        };
+        for entry in std::fs::read_dir(data.join("via_ir")).unwrap() {
+            let path = entry.unwrap().path();
+            let pgm = analyze(path.clone());
+            assert!(pgm.report.unresolved_jumps.is_empty(), "{:?}: {:?}", path, pgm.report.unresolved_jumps);
+            assert!(pgm.report.jumps_reached > 0, "{:?}", path);
+            assert_eq!(pgm.report.resolution_rate(), 1.0, "{:?}", path);
+        }
+        // The return address is pushed two blocks before each call.
+        let pgm = analyze(data.join("via_ir/early_return_address"));
+        assert!(has_edge(&pgm, 0x1f, 0x0e) && has_edge(&pgm, 0x1f, 0x1d));
+        // And here six blocks before, each call going through five JUMPDESTs.
+        let pgm = analyze(data.join("via_ir/distant_return_address"));
+        assert!(has_edge(&pgm, 0x17, 0x0a) && has_edge(&pgm, 0x17, 0x15));
+    }
+
+    #[test]
+    fn calls_are_entered_from_anywhere_in_the_frame() {
+        let code = std::fs::read_to_string(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test-data/via_ir/distant_return_address")).unwrap();
+        let mut pgm = Program::parse_bytecode(hex::decode(code.trim()).unwrap(), None);
+        pgm.gen_symbolic_edges();
+        let config = AnalysisConfig::default();
+        let path = [0x00, 0x02, 0x03, 0x04, 0x05, 0x06].map(|pc| pgm.block_idx_at(pc).unwrap());
+        let trail = path.windows(2).map(|pair| (pair[0], pair[1])).collect::<Vec<_>>();
+        let mut stack = SymbolicStack::new();
+        path.iter().for_each(|node| pgm.cfg[*node].ops.iter().for_each(|op| stack.execute(op, &pgm.code)));
+        let call = &pgm.cfg[path[5]];
+        let context = pgm.call_context_after_jump(call, U256::from(0x17), &stack, &CallContext::default(), &trail, &config);
+        assert_eq!(context.returns_to(), Some(U256::from(0x0a)));
+    }
+
+    #[test]
+    fn stale_jumpdests_enter_no_call() {
+        // 0x00: PUSH1 0x14, then a call to 0x07 returning to 0x09. Back at
+        // 0x09 the context is empty again, and both ways on to 0x11 leave
+        // the label 0x14 under a plain jump. Taking it for a return address
+        // would put 0x11 in a context of its own and explore it twice.
+        let mut pgm = Program::parse_bytecode(hex::decode("601460096007565b565b366011576011565b50005b0000").unwrap(), None);
+        pgm.gen_symbolic_edges();
+        assert!(pgm.report.unresolved_jumps.is_empty());
+        assert_eq!(pgm.report.states_explored, 5);
+    }
+
+    #[test]
+    fn bytes_are_classified() {
+        // PUSH1 4 PUSH1 0x0b PUSH1 0 CODECOPY STOP | JUMPDEST CALLER STOP | deadbeef
+        let mut pgm = Program::parse_bytecode(hex::decode("6004600b600039005b3300deadbeef0000").unwrap(), None);
+        pgm.gen_symbolic_edges();
+        let coverage = pgm.coverage();
+        assert_eq!(coverage.ranges(), vec![
+            (0..1, ByteClass::Code), (1..2, ByteClass::PushData),
+            (2..3, ByteClass::Code), (3..4, ByteClass::PushData),
+            (4..5, ByteClass::Code), (5..6, ByteClass::PushData),
+            (6..8, ByteClass::Code),
+            (8..11, ByteClass::UnreachableCode),
+            (11..15, ByteClass::Data),
+            (15..17, ByteClass::UnreachableCode),
+        ]);
+        assert_eq!(coverage.summary, CoverageSummary { code: 5, unreachable_code: 5, push_data: 3, metadata: 0, data: 4 });
+
+        let loc = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test-data/ethereum_pot");
+        let code = hex::decode(std::fs::read_to_string(loc).unwrap()).unwrap();
+        let mut pgm = Program::parse_bytecode(code.clone(), None);
+        pgm.gen_symbolic_edges();
+        // 0x29 bytes of CBOR, their length, then 11 bytes of zero padding.
+        let metadata = code.len() - 0x2b - 11..code.len();
+        assert_eq!(pgm.metadata, Some(metadata.clone()));
+        assert!(pgm.blocks().all(|block| block.pc_start < metadata.start));
+        assert_eq!(pgm.coverage().summary.metadata, 0x2b + 11);
+    }
+
+    #[test]
+    fn metadata_needs_known_keys() {
+        // PUSH1 0xa2 PUSH1 1 ADD POP STOP SDIV: with the trailing zero dropped
+        // as padding, the last two bytes read as a length of 5, reaching back
+        // to a map header, but no known key follows it.
+        let mut pgm = Program::parse_bytecode(hex::decode("60a26001015000050000").unwrap(), None);
+        pgm.gen_symbolic_edges();
+        assert_eq!(pgm.metadata, None);
+        assert_eq!(pgm.coverage().class_at(2), Some(ByteClass::Code));
+
+        // { "solc": h'000811' }, { "vyper": [0, 3, 7] } and { "abcd": h'000811' }.
+        assert_eq!(metadata_range(&hex::decode("00a164736f6c6343000811000a").unwrap()), Some(1..13));
+        assert_eq!(metadata_range(&hex::decode("00a165767970657283000307000b").unwrap()), Some(1..14));
+        assert_eq!(metadata_range(&hex::decode("00a1646162636443000811000a").unwrap()), None);
+    }
+
+    #[test]
+    fn child_contracts_are_analyzed() {
+        // CODECOPY 0x0f bytes of initcode from 0x10, CREATE, POP STOP | initcode
+        let code = "600f6010600039600f60006000f05000600480600b6000396000f333ff00000000";
+        let mut pgm = Program::parse_bytecode(hex::decode(code).unwrap(), None);
+        pgm.gen_symbolic_edges();
+        let children = pgm.children();
+        assert_eq!(children.len(), 1);
+        let child = &children[0];
+        assert_eq!((child.create_pc.as_usize(), child.opcode, child.codecopy_pc.as_usize()), (0x0d, CREATE, 0x06));
+        assert_eq!(child.initcode, 0x10..0x1f);
+        assert_eq!(child.constructor.code, hex::decode(&code[0x20..0x3e]).unwrap());
+        assert_eq!(child.runtime.as_ref().unwrap().code, hex::decode("33ff0000").unwrap());
+        assert_eq!(pgm.coverage().class_at(0x10), Some(ByteClass::Data));
+        assert!(!pgm.render().node_weights().any(|node| node.ops.contains("-> child 0")));
+        assert!(pgm.render_with_findings(pgm.children(), &[]).node_weights().any(|node| node.ops.contains("-> child 0")));
+    }
+
+    #[test]
+    fn blocks_are_lifted_to_ssa() {
+        // PUSH1 1, CALLDATASIZE, PUSH1 9, JUMPI, POP, PUSH1 2,
+        // 0x09: JUMPDEST, PUSH1 0, MSTORE, STOP
+        let code = hex::decode("6001366009575060025b60005200").unwrap();
+        let mut pgm = Program::parse_bytecode(code, None);
+        pgm.gen_symbolic_edges();
+        let ssa = pgm.ssa();
+        assert_eq!(ssa.to_string(), [
+            "block_0x0:",
+            "    v0 = 0x1",
+            "    v1 = CALLDATASIZE",
+            "    v2 = 0x9",
+            "    JUMPI v2, v1",
+            "block_0x6:",
+            "    v3 = 0x2",
+            "block_0x9:",
+            "    v4 = phi [0x0: v0], [0x6: v3]",
+            "    v5 = 0x0",
+            "    MSTORE v5, v4",
+            "block_0xd:",
+            "    STOP",
+            "",
+        ].join("\n"));
+        assert_eq!(ssa.var_count, 6);
+        assert_eq!(ssa.blocks[&U256::from(9)].entry_stack, vec![Var(4)]);
+    }
+
+    #[test]
+    fn ssa_heights_follow_the_stack() {
+        // PUSH1 1, PUSH1 2, PUSH1 3,
+        // 0x06: JUMPDEST, POP, CALLDATASIZE, PUSH1 6, JUMPI, STOP
+        // The loop drops a slot per iteration; it can never see more than three.
+        let code = hex::decode("6001600260035b50366006570000").unwrap();
+        let mut pgm = Program::parse_bytecode(code, None);
+        pgm.gen_symbolic_edges();
+        let ssa = pgm.ssa();
+        assert_eq!(ssa.blocks[&U256::from(6)].entry_stack.len(), 3);
+        assert!(ssa.blocks.values().flat_map(|block| &block.instructions).all(|inst| {
+            let op = Operation::from(revm::OpCode::try_from_u8(inst.opcode).unwrap());
+            inst.inputs.len() == op.rm_stack_count as usize
+        }));
+    }
+
+    #[test]
+    fn ssa_inputs_match_opcode_arity() {
+        // LOG2(1, 1, 1, 1), SSTORE(1, 1), POP(CALL(1, 1, 1, 1, 1, 1, 1)), SELFDESTRUCT(CALLER)
+        let code = hex::decode("6001600160016001a260016001556001600160016001600160016001f15033ff00").unwrap();
+        let mut pgm = Program::parse_bytecode(code, None);
+        pgm.gen_symbolic_edges();
+        let inputs = pgm.ssa().blocks.values().flat_map(|block| &block.instructions)
+            .map(|inst| (inst.opcode, inst.inputs.len()))
+            .collect::<HashMap<_, _>>();
+        assert_eq!(inputs[&LOG2], 4);
+        assert_eq!(inputs[&SSTORE], 2);
+        assert_eq!(inputs[&CALL], 7);
+        assert_eq!(inputs[&SELFDESTRUCT], 1);
+    }
+
+    #[test]
+    fn dataflow_solves_both_directions() {
+        /// Whether CALLDATASIZE ran on every path so far.
+        struct MustReadCalldataSize;
+        impl DataFlowAnalysis for MustReadCalldataSize {
+            type Fact = bool;
+            fn bottom(&self) -> bool { true }
+            fn boundary(&self) -> bool { false }
+            fn join(&self, fact: &mut bool, other: &bool) { *fact &= *other; }
+            fn transfer(&self, _: &Program, op: &Operation, fact: &mut bool) {
+                *fact |= op.code.u8() == CALLDATASIZE;
+            }
+        }
+        /// Whether some path from here still writes memory.
+        struct MayStoreMemory;
+        impl DataFlowAnalysis for MayStoreMemory {
+            type Fact = bool;
+            const DIRECTION: FlowDirection = FlowDirection::Backward;
+            fn bottom(&self) -> bool { false }
+            fn join(&self, fact: &mut bool, other: &bool) { *fact |= *other; }
+            fn transfer(&self, _: &Program, op: &Operation, fact: &mut bool) {
+                *fact |= op.code.u8() == MSTORE;
+            }
+        }
+
+        let code = hex::decode("6001366009575060025b60005200").unwrap();
+        let mut pgm = Program::parse_bytecode(code, None);
+        pgm.gen_symbolic_edges();
+        let (entry, merge) = (pgm.block_idx_at(0).unwrap(), pgm.block_idx_at(9).unwrap());
+
+        let must = pgm.solve(&MustReadCalldataSize);
+        assert!(!must.entry[&entry]);
+        assert!(must.entry[&merge]);
+        let at_ops = must.at_ops(&MustReadCalldataSize, &pgm, entry);
+        assert_eq!(at_ops.iter().map(|(pc, fact)| (pc.as_usize(), *fact)).collect::<Vec<_>>(),
+                   vec![(0, false), (2, false), (3, true), (5, true)]);
+
+        let may = pgm.solve(&MayStoreMemory);
+        assert!(may.entry[&entry]);
+        assert!(may.entry[&merge]);
+        assert!(!may.exit[&merge]);
+        assert!(!may.entry[&pgm.block_idx_at(0xd).unwrap()]);
+    }
+
+    #[test]
+    fn symbolic_stack_runs_other_domains() {
+        /// Whether a value was computed from calldata.
+        #[derive(Debug, Clone, PartialEq)]
+        struct FromCalldata(bool);
+        impl AbstractValue for FromCalldata {
+            fn unknown() -> Self { FromCalldata(false) }
+            fn constant(_: &[u8]) -> Self { FromCalldata(false) }
+            fn join(&self, other: &Self) -> Self { FromCalldata(self.0 || other.0) }
+            fn transfer(op: &Operation, args: &[Self]) -> Self {
+                FromCalldata(op.code.u8() == CALLDATALOAD || args.iter().any(|arg| arg.0))
+            }
+        }
+
+        // PUSH1 0, CALLDATALOAD, PUSH1 0xff, AND, PUSH1 1, SWAP1, STOP
+        let code = hex::decode("60003560ff1660019000").unwrap();
+        let pgm = Program::parse_bytecode(code.clone(), None);
+        let block = pgm.block_at(0).unwrap();
+        let stack = block.exec_symbolic(SymbolicStack::<FromCalldata>::default(), &code, block.ops.len());
+        assert_eq!(stack.peek(), FromCalldata(true));
+        assert_eq!(stack.peek_at(1), FromCalldata(false));
+
+        // The default domain folds the same block to constants where it can.
+        let stack = block.exec_symbolic(SymbolicStack::new(), &code, block.ops.len());
+        assert_eq!(stack.peek(), SymbolicStackValue::Unknown);
+        assert_eq!(stack.peek_at(1).as_u256(), Some(U256::one()));
+
+        let joined = stack.capture().join(&block.exec_symbolic(SymbolicStack::new(), &code, 3).capture());
+        assert_eq!(SymbolicStack::from(joined).peek(), SymbolicStackValue::Unknown);
+    }
+
+    #[test]
+    fn taint_reaches_sinks_through_memory() {
+        // CALLER, PUSH1 0, MSTORE,
+        // 0x04: JUMPDEST, PUSH1 1, PUSH1 0x20, PUSH1 0, SHA3, SSTORE,
+        //       PUSH1 4, CALLDATALOAD, JUMP, STOP
+        let code = hex::decode("336000525b60016020600020556004355600").unwrap();
+        let mut pgm = Program::parse_bytecode(code, None);
+        pgm.gen_symbolic_edges();
+        let flows = pgm.taint_flows().iter()
+            .map(|flow| (flow.source.kind, flow.source.pc.as_usize(), flow.sink.kind, flow.sink.pc.as_usize(), flow.path.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(flows, vec![
+            (TaintSourceKind::Caller, 0x0, TaintSinkKind::StorageSlot, 0xc, vec![U256::zero(), U256::from(4)]),
+            (TaintSourceKind::Calldata, 0xf, TaintSinkKind::JumpTarget, 0x10, vec![U256::from(4)]),
+        ]);
+
+        let pot = std::fs::read_to_string(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test-data/ethereum_pot")).unwrap();
+        let mut pgm = Program::parse_bytecode(hex::decode(pot.trim()).unwrap(), None);
+        pgm.gen_symbolic_edges();
+        assert!(pgm.taint_flows().is_empty());
+    }
+
+    #[test]
+    fn writes_after_calls_are_flagged() {
+        // call(gas(), caller(), 0, 0, 0, 0, 0), POP, SSTORE(0, 1),
+        // 0x13: call(iszero(callvalue()) * 2300, caller(), callvalue(), 0, 0, 0, 0), POP,
+        //       SSTORE(1, 2), STOP
+        let code = hex::decode(concat!(
+            "6000600060006000600033", "5af150", "6001600055",
+            "6000600060006000", "3433", "811561", "08fc02", "f150", "6002600155", "00",
+        )).unwrap();
+        let mut pgm = Program::parse_bytecode(code, None);
+        pgm.gen_symbolic_edges();
+        let findings = pgm.reentrancy().iter()
+            .map(|finding| (finding.selector, finding.call_pc.as_usize(), finding.write_pc.as_usize()))
+            .collect::<Vec<_>>();
+        assert_eq!(findings, vec![(None, 0xc, 0x12), (None, 0xc, 0x29)]);
+    }
+
+    #[test]
+    fn privileged_operations_report_their_guards() {
+        // Selector 0x11111111 self-destructs outright; 0x22222222 first
+        // requires CALLER == SLOAD(0).
+        let code = hex::decode(concat!(
+            "60003560e01c", "806311111111", "14601e57", "806322222222", "14602157", "600080fd",
+            "5b33ff",
+            "5b600054", "3314602e57", "600080fd", "5b33ff", "00",
+        )).unwrap();
+        let mut pgm = Program::parse_bytecode(code, None);
+        pgm.gen_symbolic_edges();
+        let findings = pgm.access_control().iter()
+            .map(|finding| (finding.selector, finding.pc.as_usize(), finding.operation, finding.guard))
+            .collect::<Vec<_>>();
+        assert_eq!(findings, vec![
+            (Some([0x11; 4]), 0x20, PrivilegedOp::Selfdestruct, None),
+            (Some([0x22; 4]), 0x30, PrivilegedOp::Selfdestruct, Some(Guard {
+                pc: U256::from(0x29),
+                owner: Owner::Storage(Some(U256::zero())),
+            })),
+        ]);
+    }
+
+    #[test]
+    fn guards_must_abort_other_callers() {
+        let guard = |pc: usize| Some(Guard { pc: U256::from(pc), owner: Owner::Storage(Some(U256::zero())) });
+        let findings = |code: &str| {
+            let mut pgm = Program::parse_bytecode(hex::decode(code).unwrap(), None);
+            pgm.gen_symbolic_edges();
+            pgm.access_control().iter()
+                .map(|finding| (finding.selector, finding.pc.as_usize(), finding.guard))
+                .collect::<Vec<_>>()
+        };
+        // if (caller != sload(0)) revert; selfdestruct(caller), with no dispatcher.
+        assert_eq!(findings("600054331415600b5733ff5b600080fd00"), vec![(None, 0x0a, guard(0x08))]);
+        // if (caller == sload(0)) revert; selfdestruct(caller): only the owner is kept out.
+        assert_eq!(findings("6000543314600a5733ff5b600080fd00"), vec![(None, 0x09, None)]);
+    }
+
+    #[test]
+    fn fallback_operations_are_covered() {
+        // The dispatcher from `fallback_is_where_the_last_comparison_goes`,
+        // with a fallback at 0x1c that self-destructs.
+        let code = concat!(
+            "60043610601a57", "60003560e01c8063aabbccdd14602257", "601c56",
+            "5b00", "5b33ff000000", "5b00", "00",
+        );
+        let mut pgm = Program::parse_bytecode(hex::decode(code).unwrap(), None);
+        pgm.gen_symbolic_edges();
+        let findings = pgm.access_control().iter()
+            .map(|finding| (finding.selector, finding.pc.as_usize(), finding.guard))
+            .collect::<Vec<_>>();
+        assert_eq!(findings, vec![(None, 0x1e, None)]);
+    }
+
+    #[test]
+    fn calldata_controlled_sites_are_highlighted() {
+        // SSTORE(calldataload(0x24) & 0xff, 1),
+        // 0x09: mstore(0, calldataload(4)), SSTORE(keccak256(0, 0x20), 1),
+        // 0x17: JUMP(calldataload(0x44)), STOP
+        let code = hex::decode(concat!(
+            "600160243560ff1655",
+            "60016004356000526020600020", "55",
+            "6044355600",
+        )).unwrap();
+        let mut pgm = Program::parse_bytecode(code, None);
+        pgm.gen_symbolic_edges();
+        let sites = pgm.controlled_sites().iter()
+            .map(|site| (site.kind, site.pc.as_usize(), site.calldata_offsets.iter().map(|offset| offset.as_usize()).collect::<Vec<_>>(), site.masked))
+            .collect::<Vec<_>>();
+        assert_eq!(sites, vec![
+            (ControlledSiteKind::JumpTarget, 0x1a, vec![0x44], false),
+            (ControlledSiteKind::StorageSlot, 0x8, vec![0x24], true),
+        ]);
+
+        let rendered = pgm.render_with_findings(&[], pgm.controlled_sites());
+        let entry = rendered.node_weights().find(|info| info.code_loc == 0).unwrap();
+        assert!(entry.highlight);
+        assert!(entry.ops.contains("!! arbitrary jump at 0x1a (calldata [0x44])"));
+        assert!(entry.ops.contains("!! arbitrary storage write at 0x8 (calldata [0x24], masked)"));
+        assert!(pgm.render_dot_with_findings(&[], pgm.controlled_sites()).contains("fillcolor=red"));
+        assert!(!pgm.render_dot().contains("fillcolor=red"));
+    }
+
+    #[test]
+    fn unchecked_call_results_are_found() {
+        // 0x00: POP(call(gas(), sload(0), 0, 0, 0, 0, 0))
+        // 0x10: send: POP(call(iszero(callvalue()) * 2300, caller(), callvalue(), 0, 0, 0, 0))
+        // 0x22: if iszero(iszero(call(gas(), caller(), 0, 0, 0, 0, 0))) { STOP } REVERT(0, 0)
+        let code = hex::decode(concat!(
+            "600060006000600060006000545af150",
+            "6000600060006000343381156108fc02f150",
+            "6000600060006000600033", "5af11515603857", "600080fd", "5b00",
+        )).unwrap();
+        let mut pgm = Program::parse_bytecode(code, None);
+        pgm.gen_symbolic_edges();
+        let findings = pgm.unchecked_calls().iter()
+            .map(|call| (call.pc.as_usize(), call.target.as_str(), call.send, call.kind))
+            .collect::<Vec<_>>();
+        assert_eq!(findings, vec![
+            (0x0e, "SLOAD(0x0)", false, UncheckedKind::Popped),
+            (0x20, "CALLER()", true, UncheckedKind::Popped),
+        ]);
+
+        // if call(gas(), caller(), 0, 0, 0, 0, 0) { STOP } STOP: branched on, but nothing aborts.
+        let code = hex::decode(concat!("6000600060006000600033", "5af1601157", "00", "5b00", "00")).unwrap();
+        let mut pgm = Program::parse_bytecode(code, None);
+        pgm.gen_symbolic_edges();
+        let findings = pgm.unchecked_calls().iter()
+            .map(|call| (call.pc.as_usize(), call.kind))
+            .collect::<Vec<_>>();
+        assert_eq!(findings, vec![(0x0c, UncheckedKind::NotBranchedOn)]);
+
+        // ok := call(gas(), caller(), 0, 0, 0, 0, 0)
+        // if iszero(calldatasize()) { ok := 1 } mstore(0, ok) return(0, 32)
+        // The flag only reaches the MSTORE through the phi at 0x14.
+        let code = hex::decode(concat!(
+            "6000600060006000600033", "5af1366014575060015b", "60005260206000f3", "00",
+        )).unwrap();
+        let mut pgm = Program::parse_bytecode(code, None);
+        pgm.gen_symbolic_edges();
+        assert!(!pgm.ssa().blocks[&U256::from(0x14)].phis.is_empty());
+        let findings = pgm.unchecked_calls().iter()
+            .map(|call| (call.pc.as_usize(), call.kind))
+            .collect::<Vec<_>>();
+        assert_eq!(findings, vec![(0x0c, UncheckedKind::NotBranchedOn)]);
+    }
+
+    #[test]
+    fn environment_dependence_is_classified() {
+        // 0x00: if eq(sload(0), origin()) { ... } STOP
+        // 0x09: mstore(0, timestamp()) sstore(1, keccak256(0, 32)), a timestamp-keyed slot
+        // 0x16: POP(call(gas(), caller(), number(), 0, 0, 0, 0)) STOP
+        let code = hex::decode(concat!(
+            "600054321460095700",
+            "5b426000526020600020600155",
+            "60006000600060004333", "5af15000",
+        )).unwrap();
+        let findings = |code: Vec<u8>| {
+            let mut pgm = Program::parse_bytecode(code, None);
+            pgm.gen_symbolic_edges();
+            pgm.environment_dependence().iter()
+                .map(|dep| (dep.source, dep.source_pc.as_usize(), dep.sink, dep.sink_pc.as_usize(), dep.usage))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(findings(code), vec![
+            (EnvironmentSource::Origin, 0x03, DependenceSink::Branch, 0x07, DependenceUse::Authentication),
+            (EnvironmentSource::Timestamp, 0x0a, DependenceSink::StorageWrite, 0x15, DependenceUse::Data),
+            (EnvironmentSource::Number, 0x1e, DependenceSink::CallValue, 0x21, DependenceUse::Data),
+        ]);
+
+        // 0x00: if eq(origin(), caller()) { ... }
+        // 0x06: mstore(0, timestamp()) if keccak256(0, 32) { ... } STOP
+        let code = hex::decode("3233146006575b4260005260206000206013575b0000").unwrap();
+        assert_eq!(findings(code), vec![
+            (EnvironmentSource::Origin, 0x00, DependenceSink::Branch, 0x05, DependenceUse::Data),
+            (EnvironmentSource::Timestamp, 0x07, DependenceSink::Branch, 0x12, DependenceUse::Randomness),
+        ]);
+    }
+
+    #[test]
+    fn detectors_export_sarif() {
+        // Same program as environment_dependence_is_classified.
+        let code = hex::decode(concat!(
+            "600054321460095700",
+            "5b426000526020600020600155",
+            "60006000600060004333", "5af15000",
+        )).unwrap();
+        let mut pgm = Program::parse_bytecode(code, None);
+        pgm.gen_symbolic_edges();
+        let mut registry = DetectorRegistry::default();
+        assert!(registry.disable("taint-flow"));
+        assert!(!registry.disable("no-such-detector"));
+        assert!(!registry.is_enabled("taint-flow") && registry.is_enabled("unchecked-call"));
+
+        let findings = pgm.detect(&registry);
+        let summary = findings.iter()
+            .map(|finding| (finding.id, finding.severity, finding.pcs.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(summary, vec![
+            ("environment-dependence", Severity::Medium, 0x07..0x08),
+            ("environment-dependence", Severity::Low, 0x15..0x16),
+            ("environment-dependence", Severity::Low, 0x21..0x22),
+            ("unchecked-call", Severity::Medium, 0x21..0x22),
+        ]);
+        let json = findings_json(&findings);
+        assert_eq!(json[3]["confidence"], "high");
+        assert_eq!(json[1]["related"][0]["pcs"]["start"], 0x0a);
+        assert_eq!(json[3]["related"].as_array().unwrap().len(), 0);
+
+        let sarif = registry.sarif(&findings, "contract.bin");
+        assert_eq!(sarif["version"], "2.1.0");
+        let run = &sarif["runs"][0];
+        assert_eq!(run["tool"]["driver"]["rules"].as_array().unwrap().len(), registry.names().count() - 1);
+        let result = &run["results"][1];
+        assert_eq!(result["level"], "note");
+        // Reported at the SSTORE, with the TIMESTAMP it depends on alongside.
+        assert_eq!(result["locations"][0]["physicalLocation"]["region"]["byteOffset"], 0x15);
+        assert_eq!(result["locations"][0]["physicalLocation"]["region"]["byteLength"], 1);
+        assert_eq!(result["relatedLocations"][0]["physicalLocation"]["region"]["byteOffset"], 0x0a);
+        assert_eq!(result["relatedLocations"][0]["message"]["text"], "source");
+        assert!(run["results"][3].get("relatedLocations").is_none());
+        let rule = &run["tool"]["driver"]["rules"][result["ruleIndex"].as_u64().unwrap() as usize];
+        assert_eq!(rule["id"], "environment-dependence");
+    }
+
     #[test]
     fn ethereum_pot() {
         let loc = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test-data/ethereum_pot");
@@ -366,7 +1927,7 @@ mod tests {
         buf.copy_from_slice(selector_bytes.as_slice());
         let mut pgm = Program::parse_bytecode(contract_raw.clone(), None);
         println!("Program: {:#?}", pgm);
-        println!("Block count: {}", pgm.blocks.len());
+        println!("Block count: {}", pgm.blocks().count());
         println!("Entry points count: {}", pgm.start_addresses.len());
         pgm.gen_symbolic_edges();
         let final_block_start = pgm.start_addresses.last().unwrap().clone().as_usize();
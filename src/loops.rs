@@ -0,0 +1,209 @@
+use std::collections::{BTreeSet, HashMap};
+use petgraph::stable_graph::NodeIndex;
+use petgraph::Direction;
+use revm::opcode::*;
+use crate::dataflow::{DataFlowResults, StackAnalysis};
+use crate::op::{OpType, Operation};
+use crate::stack::{AbstractValue, SymbolicStack};
+use crate::Program;
+
+/// Where the values compared by a loop's exit condition come from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum BoundSource {
+    /// PUSH immediates and arithmetic on them.
+    Constant,
+    /// SLOAD.
+    Storage,
+    /// CALLDATALOAD or CALLDATASIZE.
+    Calldata,
+    /// MLOAD, e.g. the length of an in-memory array.
+    Memory,
+    /// Any other instruction that produces a value from nothing: CALLER,
+    /// TIMESTAMP, RETURNDATASIZE, ...
+    Environment,
+    /// A value that was on the stack before anything we tracked, or a loop
+    /// with no conditional exit at all.
+    Unknown,
+}
+
+/// A natural loop: a header and every block that reaches one of its back
+/// edges without passing through the header.
+#[derive(Debug, Clone)]
+pub struct Loop {
+    pub header: NodeIndex,
+    pub body: BTreeSet<NodeIndex>,
+    /// Edges (latch, header) closing the loop.
+    pub back_edges: Vec<(NodeIndex, NodeIndex)>,
+    /// Edges from a body block to a node outside the loop.
+    pub exits: Vec<(NodeIndex, NodeIndex)>,
+    /// Index in `LoopInfo::loops` of the innermost enclosing loop.
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+    /// 1 for an outermost loop.
+    pub depth: usize,
+    /// Sources of the values feeding the conditions of the loop's JUMPI exits.
+    /// `Constant` is left out when anything else contributes, since a counter
+    /// starting from a PUSH is compared against every kind of bound.
+    pub bound: BTreeSet<BoundSource>,
+}
+
+impl Loop {
+    pub fn has_constant_bound(&self) -> bool {
+        self.bound.iter().all(|source| *source == BoundSource::Constant)
+    }
+}
+
+/// Loop nesting forest of a program's CFG. Only reducible loops are found:
+/// a retreating edge whose target does not dominate its source is not a back edge.
+#[derive(Debug, Clone, Default)]
+pub struct LoopInfo {
+    /// Outer loops before the loops nested in them.
+    pub loops: Vec<Loop>,
+}
+
+impl LoopInfo {
+    pub fn new(program: &Program) -> Self {
+        let cfg = &program.cfg;
+        let dominance = program.dominance();
+
+        let mut by_header: HashMap<NodeIndex, Vec<(NodeIndex, NodeIndex)>> = HashMap::new();
+        program.block_nodes().for_each(|latch| {
+            cfg.neighbors(latch)
+                .filter(|header| dominance.dominates(*header, latch))
+                .for_each(|header| by_header.entry(header).or_default().push((latch, header)));
+        });
+
+        let origins = program.solve(&StackOrigins::new());
+        let mut loops = by_header.into_iter().map(|(header, mut back_edges)| {
+            back_edges.sort();
+            back_edges.dedup();
+            let mut body = BTreeSet::from([header]);
+            let mut worklist = back_edges.iter().map(|(latch, _)| *latch).collect::<Vec<_>>();
+            while let Some(node) = worklist.pop() {
+                if body.insert(node) {
+                    worklist.extend(cfg.neighbors_directed(node, Direction::Incoming));
+                }
+            }
+            let exits = body.iter()
+                .flat_map(|node| cfg.neighbors(*node).map(move |next| (*node, next)))
+                .filter(|(_, next)| !body.contains(next))
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect::<Vec<_>>();
+            let bound = exit_bound(program, &origins, &exits);
+            Loop {
+                header,
+                body,
+                back_edges,
+                exits,
+                parent: None,
+                children: vec![],
+                depth: 0,
+                bound,
+            }
+        }).collect::<Vec<_>>();
+
+        // Enclosing loops have strictly larger bodies, so sorting by size
+        // puts every loop after all of its ancestors.
+        loops.sort_by_key(|lp| (std::cmp::Reverse(lp.body.len()), lp.header));
+        (0..loops.len()).for_each(|inner| {
+            let parent = (0..inner).rev().find(|outer| {
+                loops[*outer].body.is_superset(&loops[inner].body)
+            });
+            loops[inner].parent = parent;
+            loops[inner].depth = parent.map_or(1, |outer| loops[outer].depth + 1);
+            if let Some(outer) = parent {
+                loops[outer].children.push(inner);
+            }
+        });
+        LoopInfo { loops }
+    }
+
+    /// Index of the innermost loop containing `node`.
+    pub fn innermost(&self, node: NodeIndex) -> Option<usize> {
+        // Later loops are nested deeper, so the last match is the innermost.
+        self.loops.iter().rposition(|lp| lp.body.contains(&node))
+    }
+
+    /// Number of loops containing `node`.
+    pub fn depth(&self, node: NodeIndex) -> usize {
+        self.innermost(node).map_or(0, |idx| self.loops[idx].depth)
+    }
+
+    pub fn is_header(&self, node: NodeIndex) -> bool {
+        self.loops.iter().any(|lp| lp.header == node)
+    }
+}
+
+// Origin labels of a stack value, as a bitmask over `BoundSource`.
+const CONSTANT: u8 = 1 << 0;
+const STORAGE: u8 = 1 << 1;
+const CALLDATA: u8 = 1 << 2;
+const MEMORY: u8 = 1 << 3;
+const ENVIRONMENT: u8 = 1 << 4;
+const UNKNOWN: u8 = 1 << 5;
+
+/// Where a stack value may come from, as a set of `BoundSource`s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Origin(u8);
+
+impl Origin {
+    fn sources(self) -> BTreeSet<BoundSource> {
+        let mask = if self.0 == CONSTANT { self.0 } else { self.0 & !CONSTANT };
+        [
+            (CONSTANT, BoundSource::Constant),
+            (STORAGE, BoundSource::Storage),
+            (CALLDATA, BoundSource::Calldata),
+            (MEMORY, BoundSource::Memory),
+            (ENVIRONMENT, BoundSource::Environment),
+            (UNKNOWN, BoundSource::Unknown),
+        ].iter().filter(|(bit, _)| mask & bit != 0).map(|(_, source)| *source).collect()
+    }
+}
+
+impl AbstractValue for Origin {
+    fn unknown() -> Self {
+        Origin(UNKNOWN)
+    }
+
+    fn constant(_bytes: &[u8]) -> Self {
+        Origin(CONSTANT)
+    }
+
+    fn join(&self, other: &Self) -> Self {
+        Origin(self.0 | other.0)
+    }
+
+    fn transfer(op: &Operation, args: &[Self]) -> Self {
+        Origin(match op.code.u8() {
+            SLOAD => STORAGE,
+            CALLDATALOAD | CALLDATASIZE => CALLDATA,
+            MLOAD => MEMORY,
+            _ if args.is_empty() => ENVIRONMENT,
+            _ => args.iter().fold(0, |acc, arg| acc | arg.0),
+        })
+    }
+}
+
+/// Forward propagation of `Origin`s over the CFG.
+pub type StackOrigins = StackAnalysis<SymbolicStack<Origin>>;
+
+fn exit_bound(
+    program: &Program,
+    origins: &DataFlowResults<Option<SymbolicStack<Origin>>>,
+    exits: &[(NodeIndex, NodeIndex)],
+) -> BTreeSet<BoundSource> {
+    let origin = exits.iter()
+        .map(|(node, _)| *node)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .filter(|node| program.cfg[*node].ops.last().map_or(false, |op| op.category() == OpType::JumpI))
+        .map(|node| {
+            // JUMPI takes (target, condition), target on top.
+            origins.at_ops(&StackOrigins::new(), program, node).pop()
+                .and_then(|(_, stack)| stack)
+                .map_or(Origin::unknown(), |stack| stack.peek_at(1))
+        })
+        .fold(Origin(0), |acc, origin| acc.join(&origin));
+    if origin.0 == 0 { Origin::unknown() } else { origin }.sources()
+}
@@ -63,6 +63,21 @@ impl Operation {
         }
     }
 
+    /// The immediate of a PUSH, read from `code`.
+    pub fn push_value(&self, code: &[u8]) -> Option<U256> {
+        if self.category() != OpType::Push {
+            return None;
+        }
+        let start = self.pc?.as_usize() + 1;
+        let end = start + self.arg_size as usize;
+        code.get(start..end).map(U256::from_big_endian)
+    }
+
+    /// True for instructions after which execution never continues in this frame.
+    pub fn halts(&self) -> bool {
+        self.is_invalid || [STOP, RETURN, REVERT, INVALID, SELFDESTRUCT].contains(&self.code.u8())
+    }
+
 }
 
 
@@ -131,7 +131,7 @@ pub const OPCODE_STACK_ARG_DEPTH: [Option<u8>; 256] = [
     /* 0x51 */ Some(1),
     /* 0x52 */ Some(2),
     /* 0x53 */ Some(2),
-    /* 0x54 */ Some(2),
+    /* 0x54 */ Some(1),
     /* 0x55 */ Some(2),
     /* 0x56 */ Some(1),
     /* 0x57 */ Some(2),
@@ -302,11 +302,11 @@ pub const OPCODE_STACK_ARG_DEPTH: [Option<u8>; 256] = [
     /* 0xfc */ None,
     /* 0xfd */ Some(2),
     /* 0xfe */ Some(0),
-    /* 0xff */ Some(0),
+    /* 0xff */ Some(1),
 ];
 
-pub const NON_STACK_INCREASING_OPS: [u8; 36] = [
-    0x5b, 0x55, 0x56, 0x57, 0x52, 0x53, 0x3e,0x3c, 0x39, 0x37, 0xf0, 0xf3,0xfd, 0xfe, 0xff,
+pub const NON_STACK_INCREASING_OPS: [u8; 37] = [
+    0x00, 0x5b, 0x55, 0x56, 0x57, 0x52, 0x53, 0x3e,0x3c, 0x39, 0x37, 0x50, 0xf3,0xfd, 0xfe, 0xff,
     0xa0, 0xa1, 0xa2, 0xa3, 0xa4, 0x90, 0x91, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97,0x98, 0x99,
     0x9a, 0x9b, 0x9c, 0x9d, 0x9e, 0x9f
 ];
\ No newline at end of file
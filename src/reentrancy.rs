@@ -0,0 +1,117 @@
+use std::collections::BTreeSet;
+use primitive_types::U256;
+use revm::opcode::*;
+use crate::dataflow::{StackAnalysis, StackState};
+use crate::op::Operation;
+use crate::stack::{AbstractValue, SymbolicStack};
+use crate::Program;
+
+/// Gas a call can forward without letting the callee write state: the
+/// stipend `send` and `transfer` give.
+pub const CALL_STIPEND: u64 = 2300;
+
+/// A storage write that can run after an external call that forwarded
+/// enough gas to re-enter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Reentrancy {
+    /// External function both the call and the write belong to; `None` when
+    /// they are outside any recovered one.
+    pub selector: Option<[u8; 4]>,
+    pub call_pc: U256,
+    pub write_pc: U256,
+}
+
+/// An upper bound on a value, or none known. Enough to see that a call's
+/// gas is a constant or `iszero(value) * 2300`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UpperBound(pub Option<U256>);
+
+impl AbstractValue for UpperBound {
+    fn unknown() -> Self {
+        UpperBound(None)
+    }
+
+    fn constant(bytes: &[u8]) -> Self {
+        UpperBound(Some(U256::from_big_endian(bytes)))
+    }
+
+    fn join(&self, other: &Self) -> Self {
+        UpperBound(self.0.zip(other.0).map(|(a, b)| a.max(b)))
+    }
+
+    fn widen(&self, other: &Self) -> Self {
+        if self == other { *self } else { UpperBound(None) }
+    }
+
+    fn transfer(op: &Operation, args: &[Self]) -> Self {
+        let bound = |idx: usize| args.get(idx).and_then(|arg| arg.0);
+        UpperBound(match op.code.u8() {
+            ISZERO | EQ | LT | GT | SLT | SGT => Some(U256::one()),
+            BYTE => Some(U256::from(0xff)),
+            AND => args.iter().filter_map(|arg| arg.0).min(),
+            MUL => bound(0).zip(bound(1)).and_then(|(a, b)| a.checked_mul(b)),
+            ADD => bound(0).zip(bound(1)).and_then(|(a, b)| a.checked_add(b)),
+            DIV => bound(0),
+            MOD => bound(1).map(|b| b.saturating_sub(U256::one())),
+            _ => None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CallState {
+    pub stack: SymbolicStack<UpperBound>,
+    /// pcs of the calls forwarding more than the stipend made on some path here.
+    pub calls: BTreeSet<U256>,
+}
+
+impl StackState for CallState {
+    fn execute(&mut self, op: &Operation, code: &[u8]) {
+        if [CALL, CALLCODE, DELEGATECALL].contains(&op.code.u8()) {
+            let gas = self.stack.peek_at(0).0;
+            if let (true, Some(pc)) = (gas.map_or(true, |gas| gas > U256::from(CALL_STIPEND)), op.pc) {
+                self.calls.insert(pc);
+            }
+        }
+        self.stack.execute(op, code);
+    }
+
+    fn join(&mut self, other: &Self) {
+        self.stack.join(&other.stack);
+        self.calls.extend(&other.calls);
+    }
+
+    fn widen(&self, mut next: Self) -> Self {
+        next.stack = SymbolicStack::from(self.stack.capture().widen(&next.stack.capture()));
+        next
+    }
+}
+
+/// Forward may-analysis of the state-changing calls made so far.
+pub type CallsMade = StackAnalysis<CallState>;
+
+/// Every SSTORE some path reaches after a CALL, CALLCODE or DELEGATECALL
+/// whose gas is not bounded by the stipend, once per external function
+/// holding both.
+pub fn reentrancy(program: &Program) -> Vec<Reentrancy> {
+    let results = program.solve(&CallsMade::new());
+    let dispatcher = program.dispatcher();
+    let mut findings = BTreeSet::new();
+    program.block_nodes().for_each(|write_node| {
+        let ops = &program.cfg[write_node].ops;
+        results.at_ops(&CallsMade::new(), program, write_node).into_iter().zip(ops).for_each(|((write_pc, state), op)| {
+            let calls = match state {
+                Some(state) if op.code.u8() == SSTORE => state.calls,
+                _ => return,
+            };
+            calls.into_iter().for_each(|call_pc| {
+                let call_node = program.node_containing(call_pc.as_usize());
+                let selectors = call_node.map_or(vec![None], |call_node| dispatcher.selectors_containing(&[write_node, call_node]));
+                selectors.into_iter().for_each(|selector| {
+                    findings.insert(Reentrancy { selector, call_pc, write_pc });
+                });
+            });
+        });
+    });
+    findings.into_iter().collect()
+}
@@ -0,0 +1,371 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use petgraph::stable_graph::NodeIndex;
+use petgraph::Direction;
+use primitive_types::U256;
+use revm::opcode::*;
+use crate::op::{OpType, Operation};
+use crate::{BlockKind, Program, MAX_STACK_DEPTH};
+
+/// An SSA variable, printed as `v7`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Var(pub usize);
+
+impl fmt::Display for Var {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "v{}", self.0)
+    }
+}
+
+/// `output = phi [pred: var, ...]` at the start of a block. A phi without
+/// inputs stands for a stack slot no known predecessor provides.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Phi {
+    pub output: Var,
+    /// Start of each predecessor block and the variable it passes in.
+    pub inputs: Vec<(U256, Var)>,
+}
+
+/// One instruction with its stack operands made explicit, in the order the
+/// EVM pops them: `v7 = ADD v3, v5` adds the old top `v3` to `v5`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instruction {
+    pub pc: U256,
+    pub opcode: u8,
+    pub output: Option<Var>,
+    pub inputs: Vec<Var>,
+    /// The value of a PUSH.
+    pub immediate: Option<U256>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SsaBlock {
+    pub pc: U256,
+    pub phis: Vec<Phi>,
+    /// Every instruction but DUP, SWAP and POP, which only rename stack slots,
+    /// and JUMPDEST.
+    pub instructions: Vec<Instruction>,
+    /// Variables on the stack when the block is entered, bottom first, as
+    /// deep as this block or any successor reads.
+    pub entry_stack: Vec<Var>,
+    /// Variables on the stack when the block is left, bottom first.
+    pub exit_stack: Vec<Var>,
+}
+
+/// Where a variable gets its value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Definition<'a> {
+    Phi(&'a Phi),
+    Instruction(&'a Instruction),
+}
+
+/// A program lifted to SSA over its resolved CFG.
+#[derive(Debug, Clone, Default)]
+pub struct SsaProgram {
+    /// Keyed by block start.
+    pub blocks: BTreeMap<U256, SsaBlock>,
+    pub var_count: usize,
+}
+
+impl SsaProgram {
+    /// Lifts every code block. Entry stack depths come from a stack-height
+    /// analysis over the CFG's code edges, so jumps left unresolved cut the
+    /// data flow along them.
+    pub fn lift(program: &Program) -> Self {
+        let heights = entry_heights(program);
+        let mut next_var = 0;
+        let mut fresh = || {
+            next_var += 1;
+            Var(next_var - 1)
+        };
+
+        let mut blocks = BTreeMap::new();
+        program.block_nodes().for_each(|node| {
+            let block = &program.cfg[node];
+            let mut entry_stack = (0..heights[&node]).map(|_| fresh()).collect::<Vec<_>>();
+            let mut stack = entry_stack.clone();
+            let mut instructions = vec![];
+            block.ops.iter().for_each(|op| {
+                match op.category() {
+                    OpType::Dup => {
+                        let depth = (op.code.u8() - DUP1 + 1) as usize;
+                        reach(&mut stack, &mut entry_stack, depth, &mut fresh);
+                        stack.push(stack[stack.len() - depth]);
+                    },
+                    OpType::Swap => {
+                        let depth = (op.code.u8() - SWAP1 + 1) as usize;
+                        reach(&mut stack, &mut entry_stack, depth + 1, &mut fresh);
+                        let len = stack.len();
+                        stack.swap(len - 1, len - 1 - depth);
+                    },
+                    OpType::Pop => {
+                        reach(&mut stack, &mut entry_stack, 1, &mut fresh);
+                        stack.pop();
+                    },
+                    _ if op.code.u8() == JUMPDEST => {},
+                    _ => {
+                        reach(&mut stack, &mut entry_stack, op.rm_stack_count as usize, &mut fresh);
+                        let inputs = stack.split_off(stack.len() - op.rm_stack_count as usize).into_iter().rev().collect();
+                        let output = (op.add_stack_count > 0).then(&mut fresh);
+                        stack.extend(output);
+                        instructions.push(Instruction {
+                            pc: op.pc.unwrap_or_default(),
+                            opcode: op.code.u8(),
+                            output,
+                            inputs,
+                            immediate: op.push_value(&program.code),
+                        });
+                    },
+                }
+            });
+            blocks.insert(node, SsaBlock {
+                pc: block.id(),
+                phis: vec![],
+                instructions,
+                entry_stack,
+                exit_stack: stack,
+            });
+        });
+
+        // Every entry slot starts out as a phi over what each predecessor
+        // leaves at the same depth from the top.
+        let phis = blocks.iter().map(|(node, block)| {
+            let mut preds = program.cfg.neighbors_directed(*node, Direction::Incoming)
+                .filter(|pred| blocks.contains_key(pred))
+                .collect::<Vec<_>>();
+            preds.sort();
+            preds.dedup();
+            let height = block.entry_stack.len();
+            let phis = block.entry_stack.iter().enumerate().map(|(idx, output)| {
+                let depth = height - idx;
+                let inputs = preds.iter().filter_map(|pred| {
+                    let exit = &blocks[pred].exit_stack;
+                    exit.len().checked_sub(depth).map(|idx| (blocks[pred].pc, exit[idx]))
+                }).collect::<Vec<_>>();
+                Phi { output: *output, inputs }
+            }).collect::<Vec<_>>();
+            (*node, phis)
+        }).collect::<Vec<_>>();
+        phis.into_iter().for_each(|(node, phis)| {
+            if let Some(block) = blocks.get_mut(&node) {
+                block.phis = phis;
+            }
+        });
+
+        let mut ssa = SsaProgram {
+            blocks: blocks.into_values().map(|block| (block.pc, block)).collect(),
+            var_count: next_var,
+        };
+        ssa.remove_trivial_phis();
+        ssa.renumber();
+        ssa
+    }
+
+    /// The phi or instruction defining each variable.
+    pub fn definitions(&self) -> HashMap<Var, Definition<'_>> {
+        self.blocks.values().flat_map(|block| {
+            let phis = block.phis.iter().map(|phi| (phi.output, Definition::Phi(phi)));
+            let insts = block.instructions.iter()
+                .filter_map(|inst| inst.output.map(|output| (output, Definition::Instruction(inst))));
+            phis.chain(insts)
+        }).collect()
+    }
+
+    /// `var` written out as nested operations, `AND(0xff, SLOAD(0x0))`, down
+    /// to `depth` levels; deeper variables and phis are left as names.
+    pub fn expression(&self, var: Var, depth: usize) -> String {
+        self.expression_in(&self.definitions(), var, depth)
+    }
+
+    /// `expression` against definitions already collected, for callers
+    /// writing out many variables.
+    pub(crate) fn expression_in(&self, definitions: &HashMap<Var, Definition<'_>>, var: Var, depth: usize) -> String {
+        match definitions.get(&var) {
+            Some(Definition::Instruction(inst)) if depth > 0 => match inst.immediate {
+                Some(value) => format!("{:#x}", value),
+                None => {
+                    let inputs = inst.inputs.iter()
+                        .map(|input| self.expression_in(definitions, *input, depth - 1))
+                        .collect::<Vec<_>>();
+                    format!("{}({})", opcode_name(inst.opcode), inputs.join(", "))
+                },
+            },
+            _ => var.to_string(),
+        }
+    }
+
+    /// Drops phis whose inputs are all one variable (or the phi itself),
+    /// replacing their uses, until none are left.
+    fn remove_trivial_phis(&mut self) {
+        let mut replaced: HashMap<Var, Var> = HashMap::new();
+        let resolve = |replaced: &HashMap<Var, Var>, mut var: Var| {
+            while let Some(next) = replaced.get(&var) {
+                var = *next;
+            }
+            var
+        };
+        loop {
+            let mut changed = false;
+            self.blocks.values_mut().for_each(|block| {
+                block.phis.retain(|phi| {
+                    let mut sources = phi.inputs.iter()
+                        .map(|(_, var)| resolve(&replaced, *var))
+                        .filter(|var| *var != phi.output);
+                    let first = match sources.next() {
+                        Some(first) => first,
+                        None => return true,
+                    };
+                    if sources.all(|var| var == first) {
+                        replaced.insert(phi.output, first);
+                        changed = true;
+                        false
+                    } else {
+                        true
+                    }
+                });
+            });
+            if !changed {
+                break;
+            }
+        }
+        self.rename(|var| resolve(&replaced, var));
+    }
+
+    /// Numbers variables in order of definition.
+    fn renumber(&mut self) {
+        let mut numbers = HashMap::new();
+        self.blocks.values().for_each(|block| {
+            let defs = block.phis.iter().map(|phi| phi.output)
+                .chain(block.instructions.iter().filter_map(|inst| inst.output));
+            defs.for_each(|var| {
+                let next = Var(numbers.len());
+                numbers.entry(var).or_insert(next);
+            });
+        });
+        self.var_count = numbers.len();
+        self.rename(|var| numbers.get(&var).copied().unwrap_or(var));
+    }
+
+    fn rename(&mut self, rename: impl Fn(Var) -> Var) {
+        self.blocks.values_mut().for_each(|block| {
+            block.phis.iter_mut().for_each(|phi| {
+                phi.output = rename(phi.output);
+                phi.inputs.iter_mut().for_each(|(_, var)| *var = rename(*var));
+            });
+            block.instructions.iter_mut().for_each(|inst| {
+                inst.output = inst.output.map(&rename);
+                inst.inputs.iter_mut().for_each(|var| *var = rename(*var));
+            });
+            block.entry_stack.iter_mut().for_each(|var| *var = rename(*var));
+            block.exit_stack.iter_mut().for_each(|var| *var = rename(*var));
+        });
+    }
+}
+
+impl fmt::Display for SsaProgram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for block in self.blocks.values() {
+            writeln!(f, "block_{:#x}:", block.pc)?;
+            for phi in &block.phis {
+                let inputs = phi.inputs.iter()
+                    .map(|(pred, var)| format!("[{:#x}: {}]", pred, var))
+                    .collect::<Vec<_>>();
+                writeln!(f, "    {} = phi {}", phi.output, inputs.join(", "))?;
+            }
+            for inst in &block.instructions {
+                write!(f, "    ")?;
+                if let Some(output) = inst.output {
+                    write!(f, "{} = ", output)?;
+                }
+                match inst.immediate {
+                    Some(value) => write!(f, "{:#x}", value)?,
+                    None => {
+                        let name = opcode_name(inst.opcode);
+                        let inputs = inst.inputs.iter().map(|var| var.to_string()).collect::<Vec<_>>();
+                        write!(f, "{}", name)?;
+                        if !inputs.is_empty() {
+                            write!(f, " {}", inputs.join(", "))?;
+                        }
+                    },
+                }
+                writeln!(f)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn opcode_name(opcode: u8) -> &'static str {
+    revm::OpCode::try_from_u8(opcode).map_or("INVALID", |op| op.as_str())
+}
+
+/// Names deeper entry slots until `stack` is `depth` tall, so an operation
+/// reading below what its block was given still gets all its operands.
+fn reach(stack: &mut Vec<Var>, entry_stack: &mut Vec<Var>, depth: usize, fresh: &mut impl FnMut() -> Var) {
+    while stack.len() < depth {
+        let var = fresh();
+        stack.insert(0, var);
+        entry_stack.insert(0, var);
+    }
+}
+
+/// How far below its entry height a block reads, and its net height change.
+fn stack_effect(ops: &[Operation]) -> (usize, isize) {
+    let mut height = 0_isize;
+    let mut deepest = 0_isize;
+    ops.iter().for_each(|op| {
+        let reads = match op.category() {
+            OpType::Dup => (op.code.u8() - DUP1 + 1) as isize,
+            OpType::Swap => (op.code.u8() - SWAP1 + 2) as isize,
+            _ => op.rm_stack_count as isize,
+        };
+        deepest = deepest.max(reads - height);
+        height += op.add_stack_count as isize - op.rm_stack_count as isize;
+    });
+    (deepest as usize, height)
+}
+
+/// Entry stack depth each block must name: what it reads itself, plus what
+/// its successors read through it, to a fixpoint. Successors' needs are only
+/// passed on as deep as the stack can actually be on entry, so a loop that
+/// shrinks the stack does not inflate its blocks to `MAX_STACK_DEPTH`.
+fn entry_heights(program: &Program) -> HashMap<NodeIndex, usize> {
+    let effects = program.block_nodes()
+        .map(|node| (node, stack_effect(&program.cfg[node].ops)))
+        .collect::<HashMap<_, _>>();
+    let code_successors = |node: NodeIndex| program.cfg.neighbors(node)
+        .filter(|next| program.cfg[*next].kind == BlockKind::Code);
+
+    // The tallest stack each block is entered with, from pc 0 along the
+    // blocks' net height changes. Blocks never reached from pc 0 have none.
+    let mut available = HashMap::new();
+    let mut queue = program.block_idx_at(0).into_iter().collect::<VecDeque<_>>();
+    queue.iter().for_each(|start| { available.insert(*start, 0_usize); });
+    while let Some(node) = queue.pop_front() {
+        let exit = (available[&node] as isize + effects[&node].1).clamp(0, MAX_STACK_DEPTH as isize) as usize;
+        code_successors(node).for_each(|next| {
+            if available.get(&next).map_or(true, |height| exit > *height) {
+                available.insert(next, exit);
+                queue.push_back(next);
+            }
+        });
+    }
+
+    let mut heights = effects.iter().map(|(node, (reads, _))| (*node, *reads)).collect::<HashMap<_, _>>();
+    let mut queue = program.block_nodes().collect::<VecDeque<_>>();
+    while let Some(node) = queue.pop_front() {
+        let (reads, delta) = effects[&node];
+        let cap = available.get(&node).copied().unwrap_or(0).max(reads);
+        let needed = code_successors(node)
+            .map(|next| heights[&next] as isize - delta)
+            .max()
+            .unwrap_or(0)
+            .clamp(0, cap as isize) as usize;
+        if needed > heights[&node] {
+            heights.insert(node, needed);
+            queue.extend(program.cfg.neighbors_directed(node, Direction::Incoming)
+                .filter(|pred| program.cfg[*pred].kind == BlockKind::Code));
+        }
+    }
+    heights
+}
@@ -1,193 +1,258 @@
+use std::fmt::Debug;
 use crate::op::*;
-#[derive(Clone, Debug, Copy)]
+use crate::MAX_STACK_DEPTH;
+use primitive_types::U256;
+use revm::opcode::*;
+
+/// A lattice of abstract stack values `SymbolicStack` can run over. DUP, SWAP
+/// and POP only move values around; every other opcode goes through
+/// `transfer`.
+pub trait AbstractValue: Clone + PartialEq + Debug {
+    /// A value nothing is known about.
+    fn unknown() -> Self;
+
+    /// The value of a slot that was never written.
+    fn uninitialized() -> Self {
+        Self::unknown()
+    }
+
+    /// The value of a PUSH, big-endian and at most 32 bytes.
+    fn constant(bytes: &[u8]) -> Self;
+
+    /// Least upper bound of two values where paths meet.
+    fn join(&self, other: &Self) -> Self;
+
+    /// Used instead of `join` when a state is revisited, so domains of
+    /// unbounded height still converge.
+    fn widen(&self, other: &Self) -> Self {
+        self.join(other)
+    }
+
+    /// The value `op` pushes, given the values it pops, top first. Only
+    /// called for operations that push one.
+    fn transfer(op: &Operation, args: &[Self]) -> Self;
+
+    /// The concrete value, if this is one.
+    fn as_u256(&self) -> Option<U256> {
+        None
+    }
+}
+
+/// The default domain: a known 32-byte word or nothing at all.
+#[derive(Clone, Debug, Copy, PartialEq, Eq, Hash)]
 pub enum SymbolicStackValue {
-    Data([u8; 256]),
+    Data([u8; 32]),
     Unknown,
     Uninitialized
 }
 
 impl SymbolicStackValue {
-    pub fn inner(&self) -> Option<&[u8; 256]> {
+    pub fn inner(&self) -> Option<&[u8; 32]> {
         if let SymbolicStackValue::Data(dat) = self {
             Some(dat)
         } else {
             None
         }
     }
-}
-#[derive(Debug, Clone, Copy)]
-pub struct SymbolicStackFrame {
-    contents: SymbolicStackValue
+
+    pub fn as_u256(&self) -> Option<U256> {
+        self.inner().map(|dat| U256::from_big_endian(dat))
+    }
 }
 
-impl SymbolicStackFrame {
-    pub fn new() -> Self {
-        Self {
-            contents: SymbolicStackValue::Uninitialized
-        }
+impl AbstractValue for SymbolicStackValue {
+    fn unknown() -> Self {
+        SymbolicStackValue::Unknown
     }
 
-    pub fn peek(&self) -> SymbolicStackValue {
-        self.contents.clone()
+    fn uninitialized() -> Self {
+        SymbolicStackValue::Uninitialized
     }
 
-    pub fn inner(&self) -> &SymbolicStackValue {
-        &self.contents
+    fn constant(bytes: &[u8]) -> Self {
+        // Values are kept big-endian and right-aligned so bytewise ops line up.
+        let mut data = [0u8; 32];
+        data[32 - bytes.len()..].copy_from_slice(bytes);
+        SymbolicStackValue::Data(data)
     }
-    pub fn new_with_unknown_val() -> Self {
-        Self {
-            contents: SymbolicStackValue::Unknown
+
+    fn join(&self, other: &Self) -> Self {
+        if self == other {
+            *self
+        } else {
+            SymbolicStackValue::Unknown
         }
     }
 
-    pub fn write(&mut self, val: &[u8]) {
-        let mut data = [0u8; 256];
-        data[..val.len()].copy_from_slice(val);
-        self.contents = SymbolicStackValue::Data(data);
+    fn transfer(op: &Operation, args: &[Self]) -> Self {
+        match (op.category(), args) {
+            (OpType::And, [SymbolicStackValue::Data(top), SymbolicStackValue::Data(second)]) => {
+                let mut res = [0u8; 32];
+                res.iter_mut().enumerate().for_each(|(i, byte)| *byte = top[i] & second[i]);
+                SymbolicStackValue::Data(res)
+            },
+            _ => SymbolicStackValue::Unknown,
+        }
     }
 
-
+    fn as_u256(&self) -> Option<U256> {
+        SymbolicStackValue::as_u256(self)
+    }
 }
 
-#[derive(Debug, Clone)]
-pub struct SymbolicStack {
-    pub frames: [SymbolicStackFrame; 1024],
+/// The EVM stack over an abstract domain. `frames[0]` is never written, so the
+/// live stack is `frames[1..=pc]`; reading below it gives unknown values.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SymbolicStack<V = SymbolicStackValue> {
+    pub frames: Vec<V>,
     pc: usize,
 }
 
-pub struct SymbolicStackCapture {
+#[derive(Debug, Clone, PartialEq)]
+pub struct SymbolicStackCapture<V = SymbolicStackValue> {
     pub frame_count: usize,
     pub pc: usize,
-    pub vals: Box<Vec<SymbolicStackFrame>>
+    pub vals: Box<Vec<V>>
 }
 
 
-impl From<SymbolicStackCapture> for SymbolicStack {
-    fn from(capture: SymbolicStackCapture) -> Self {
-        let mut frames = [SymbolicStackFrame::new(); 1024];
-        frames[..capture.vals.len()].copy_from_slice(&capture.vals);
+impl<V: AbstractValue> From<SymbolicStackCapture<V>> for SymbolicStack<V> {
+    fn from(capture: SymbolicStackCapture<V>) -> Self {
         Self {
-            frames ,
+            frames: *capture.vals,
             pc: capture.pc,
         }
     }
 }
-impl SymbolicStack {
-    pub fn capture(&self) -> SymbolicStackCapture {
-        let stack = self;
-        let mut i = 0;
-        let mut len = 0;
-        loop {
-            match stack.frames[i].inner() {
-                &SymbolicStackValue::Uninitialized => {
-                    len = i + 1;
-                    break;
-                },
-                _ => {
-                    i += 1;
-                }
-            }
+impl<V: AbstractValue> SymbolicStackCapture<V> {
+    /// Frame-wise join of two captures, aligned at the top of the stack.
+    /// The result is as tall as the shorter input; anything below it reads
+    /// as unknown on pop.
+    pub fn join(&self, other: &SymbolicStackCapture<V>) -> SymbolicStackCapture<V> {
+        self.merge(other, V::join)
+    }
+
+    /// Like `join`, but widening each frame.
+    pub fn widen(&self, other: &SymbolicStackCapture<V>) -> SymbolicStackCapture<V> {
+        self.merge(other, V::widen)
+    }
+
+    fn merge(&self, other: &SymbolicStackCapture<V>, merge: impl Fn(&V, &V) -> V) -> SymbolicStackCapture<V> {
+        let height = self.pc.min(other.pc);
+        let mut vals = vec![V::uninitialized(); height + 1];
+        (0..height).for_each(|depth| {
+            vals[height - depth] = merge(&self.vals[self.pc - depth], &other.vals[other.pc - depth]);
+        });
+        SymbolicStackCapture {
+            frame_count: height + 1,
+            pc: height,
+            vals: Box::new(vals),
+        }
+    }
+}
+
+impl<V: AbstractValue> Default for SymbolicStack<V> {
+    fn default() -> Self {
+        Self {
+            pc: 0,
+            frames: vec![V::uninitialized()],
         }
+    }
+}
+
+impl SymbolicStack {
+    /// An empty stack over the default domain; use `default()` for others.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
 
-        let vals = Box::new(stack.frames[0..len].to_vec());
+impl<V: AbstractValue> SymbolicStack<V> {
+    pub fn capture(&self) -> SymbolicStackCapture<V> {
+        let len = self.pc + 1;
+        let vals = Box::new(self.frames[0..len].to_vec());
         SymbolicStackCapture {
             frame_count: len,
-            pc: stack.pc,
+            pc: self.pc,
             vals,
         }
     }
-    pub fn peek(&self) -> SymbolicStackValue {
-        self.frames[self.pc].peek()
+    pub fn peek(&self) -> V {
+        self.frames[self.pc].clone()
     }
-    pub fn new() -> Self {
-        Self {
-            pc: 0,
-            frames: [SymbolicStackFrame::new(); 1024]
+
+    /// The value `depth` frames below the top; unknown past the tracked frames.
+    pub fn peek_at(&self, depth: usize) -> V {
+        if depth >= self.pc {
+            V::unknown()
+        } else {
+            self.frames[self.pc - depth].clone()
         }
     }
-    pub fn pop(&mut self) -> SymbolicStackFrame {
+
+    /// Whether any tracked frame holds exactly `val`.
+    pub fn holds(&self, val: U256) -> bool {
+        self.frames[1..=self.pc].iter().any(|frame| frame.as_u256() == Some(val))
+    }
+    pub fn pop(&mut self) -> V {
+        // A block can be reached with fewer tracked frames than it consumes;
+        // anything below what we track is unknown rather than an error.
+        if self.pc == 0 {
+            return V::unknown();
+        }
         self.pc -= 1;
-        self.frames[self.pc + 1].clone()
+        self.frames.pop().unwrap_or_else(V::unknown)
     }
 
-    pub fn push(&mut self, val: Option<&[u8]>) {
-        self.pc += 1;
-        if let Some(val) = val {
-            self.frames[self.pc].write(val);
-        } else {
-            self.frames[self.pc] = SymbolicStackFrame::new_with_unknown_val();
+    pub fn push(&mut self, val: V) {
+        if self.pc + 1 >= MAX_STACK_DEPTH as usize {
+            return;
         }
-
+        self.pc += 1;
+        self.frames.push(val);
     }
 
     pub fn execute(&mut self, op: &Operation, code: &[u8]) {
         match op.category() {
-            OpType::And => {
-                // frames[self.pc] & frames[self.pc - 1]
-                let top = self.pop();
-                let top = top.inner().inner();
-                let second = self.pop();
-                let second = second.inner().inner();
-                if top.is_none() || second.is_none() {
-                    self.push(Some([0u8;256].as_slice()));
-                    return;
-                }
-                let top = top.unwrap();
-                let second = second.unwrap();
-                let mut res = [0u8; 256];
-                let mut i = 0;
-                while i < 256 {
-                    let byte_res = top[i] & second[i];
-                    res[i] = byte_res;
-                    i += 1;
-                }
-                self.push(Some(res.as_slice()));
-            },
             OpType::Pop => {
                 self.pop();
             },
             OpType::Push => {
-                println!("OP IN STACK EXECUTE: {:?}", op);
                 let push_byte_len = op.arg_size as usize;
                 let start_loc = op.pc.unwrap().as_usize() + 1;
                 let end_loc = start_loc + push_byte_len;
-                let push_bytes = &code[start_loc..end_loc];
-                self.push(Some(push_bytes));
+                if end_loc > code.len() {
+                    // PUSH truncated by the end of the code.
+                    self.push(V::unknown());
+                    return;
+                }
+                self.push(V::constant(&code[start_loc..end_loc]));
             },
             OpType::Swap => {
-                let swap_frame_count = op.rm_stack_count as usize;
-                let top_addr = self.pc;
-                let swap_addr = (self.pc - swap_frame_count) + 1;
-                let temp = self.frames[top_addr].clone();
-                self.frames[self.pc] = self.frames[swap_addr].clone();
-                self.frames[swap_addr] = temp;
+                let swap_depth = (op.code.u8() - SWAP1 + 1) as usize;
+                if self.pc <= swap_depth {
+                    self.frames[self.pc] = V::unknown();
+                    return;
+                }
+                self.frames.swap(self.pc, self.pc - swap_depth);
             },
             OpType::Dup => {
-                let dup_frame_count = op.rm_stack_count as usize;
-
-                let dup_target = self.frames[(self.pc - dup_frame_count) + 1].clone();
-                let dup_target = dup_target.inner().inner();
-                if let Some(val) = dup_target {
-                    self.push(Some(val.as_slice()));
-                } else {
-                    self.push(None);
+                let dup_depth = (op.code.u8() - DUP1 + 1) as usize;
+                if self.pc < dup_depth {
+                    self.push(V::unknown());
+                    return;
                 }
-
-
+                let dup_target = self.frames[(self.pc - dup_depth) + 1].clone();
+                self.push(dup_target);
             }
             _ => {
-                // Other, JumpI, Jump
-                let rm_stack_count = op.rm_stack_count as usize;
-                let add_stack_count = op.add_stack_count as usize;
-                (1..rm_stack_count).into_iter().for_each(|_| {
-                    self.pop();
-                });
-                (1..add_stack_count).into_iter().for_each(|_| {
-                    self.push(None);
+                // And, Other, JumpI, Jump
+                let args = (0..op.rm_stack_count).map(|_| self.pop()).collect::<Vec<_>>();
+                (0..op.add_stack_count).for_each(|_| {
+                    self.push(V::transfer(op, &args));
                 });
-
             },
         }
     }
 
-}
\ No newline at end of file
+}
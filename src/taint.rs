@@ -0,0 +1,274 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use petgraph::stable_graph::NodeIndex;
+use primitive_types::U256;
+use revm::opcode::*;
+use crate::dataflow::{DataFlowResults, StackAnalysis, StackState};
+use crate::op::Operation;
+use crate::stack::{AbstractValue, SymbolicStack, SymbolicStackValue};
+use crate::{BlockKind, Program};
+
+/// Where attacker-controlled data enters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum TaintSourceKind {
+    /// CALLDATALOAD, or memory written by CALLDATACOPY.
+    Calldata,
+    Caller,
+    Origin,
+    /// RETURNDATASIZE, or memory written by RETURNDATACOPY or a call's output.
+    ReturnData,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TaintSource {
+    pub pc: U256,
+    pub kind: TaintSourceKind,
+}
+
+/// The operand of a dangerous operation a tainted value reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum TaintSinkKind {
+    /// Target of a JUMP or JUMPI.
+    JumpTarget,
+    /// Slot of an SSTORE.
+    StorageSlot,
+    /// Address of a CALL, CALLCODE or DELEGATECALL.
+    CallTarget,
+    /// Value of a CALL or CALLCODE.
+    CallValue,
+    SelfdestructBeneficiary,
+    Create2Salt,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TaintSink {
+    pub pc: U256,
+    pub kind: TaintSinkKind,
+}
+
+/// A source whose data can reach a sink.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TaintFlow {
+    pub source: TaintSource,
+    pub sink: TaintSink,
+    /// Starts of the blocks on a shortest CFG path from the source to the sink.
+    pub path: Vec<U256>,
+}
+
+pub type Taint = BTreeSet<TaintSource>;
+
+/// A stack value in the default domain, plus the sources it was computed from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaintedValue {
+    pub value: SymbolicStackValue,
+    pub taint: Taint,
+}
+
+impl AbstractValue for TaintedValue {
+    fn unknown() -> Self {
+        TaintedValue { value: SymbolicStackValue::Unknown, taint: Taint::new() }
+    }
+
+    fn uninitialized() -> Self {
+        TaintedValue { value: SymbolicStackValue::Uninitialized, taint: Taint::new() }
+    }
+
+    fn constant(bytes: &[u8]) -> Self {
+        TaintedValue { value: SymbolicStackValue::constant(bytes), taint: Taint::new() }
+    }
+
+    fn join(&self, other: &Self) -> Self {
+        TaintedValue {
+            value: self.value.join(&other.value),
+            taint: self.taint.union(&other.taint).copied().collect(),
+        }
+    }
+
+    fn transfer(op: &Operation, args: &[Self]) -> Self {
+        let values = args.iter().map(|arg| arg.value).collect::<Vec<_>>();
+        let mut taint = args.iter().flat_map(|arg| arg.taint.iter().copied()).collect::<Taint>();
+        let kind = match op.code.u8() {
+            CALLDATALOAD => Some(TaintSourceKind::Calldata),
+            CALLER => Some(TaintSourceKind::Caller),
+            ORIGIN => Some(TaintSourceKind::Origin),
+            RETURNDATASIZE => Some(TaintSourceKind::ReturnData),
+            _ => None,
+        };
+        if let (Some(kind), Some(pc)) = (kind, op.pc) {
+            taint.insert(TaintSource { pc, kind });
+        }
+        TaintedValue { value: SymbolicStackValue::transfer(op, &values), taint }
+    }
+
+    fn as_u256(&self) -> Option<U256> {
+        self.value.as_u256()
+    }
+}
+
+/// Copies longer than this, or at unknown offsets, taint all of memory.
+const MAX_TRACKED_COPY: usize = 0x1000;
+
+/// Taint of memory, per 32-byte word at constant offsets, plus whatever was
+/// written at offsets that are not known. Generic over what a taint is made
+/// of, so other analyses can follow their own sources through memory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaintedMemory<T = TaintSource> {
+    words: BTreeMap<usize, BTreeSet<T>>,
+    anywhere: BTreeSet<T>,
+}
+
+impl<T> Default for TaintedMemory<T> {
+    fn default() -> Self {
+        TaintedMemory { words: BTreeMap::new(), anywhere: BTreeSet::new() }
+    }
+}
+
+impl<T: Ord + Copy> TaintedMemory<T> {
+    fn region(offset: &impl AbstractValue, size: Option<U256>) -> Option<(usize, usize)> {
+        let (offset, size) = (offset.as_u256()?, size?);
+        let end = offset.checked_add(size)?;
+        (size <= U256::from(MAX_TRACKED_COPY) && end <= U256::from(u32::MAX))
+            .then(|| (offset.as_usize(), size.as_usize()))
+    }
+
+    /// Overwrites `size` bytes from `offset` with `taint`.
+    pub(crate) fn write(&mut self, offset: &impl AbstractValue, size: Option<U256>, taint: &BTreeSet<T>) {
+        match Self::region(offset, size) {
+            Some((offset, size)) => (offset..offset + size).step_by(32).for_each(|word| {
+                self.words.insert(word, taint.clone());
+            }),
+            None => self.anywhere.extend(taint),
+        }
+    }
+
+    pub(crate) fn read(&self, offset: &impl AbstractValue, size: Option<U256>) -> BTreeSet<T> {
+        let mut taint = self.anywhere.clone();
+        match Self::region(offset, size) {
+            Some((offset, size)) => self.words.range(offset.saturating_sub(31)..offset + size.max(1))
+                .for_each(|(_, word)| taint.extend(word)),
+            None => self.words.values().for_each(|word| taint.extend(word)),
+        }
+        taint
+    }
+
+    pub(crate) fn join(&mut self, other: &TaintedMemory<T>) {
+        other.words.iter().for_each(|(offset, taint)| self.words.entry(*offset).or_default().extend(taint));
+        self.anywhere.extend(&other.anywhere);
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TaintState {
+    pub stack: SymbolicStack<TaintedValue>,
+    pub memory: TaintedMemory,
+}
+
+impl StackState for TaintState {
+    fn execute(&mut self, op: &Operation, code: &[u8]) {
+        let args = (0..7).map(|depth| self.stack.peek_at(depth)).collect::<Vec<_>>();
+        let source = |kind| op.pc.map(|pc| Taint::from([TaintSource { pc, kind }])).unwrap_or_default();
+        // Memory effects, in terms of the operands before the op pops them.
+        let mut read = None;
+        match op.code.u8() {
+            MLOAD => read = Some((args[0].clone(), Some(U256::from(32)))),
+            SHA3 => read = Some((args[0].clone(), args[1].as_u256())),
+            MSTORE => self.memory.write(&args[0], Some(U256::from(32)), &args[1].taint),
+            MSTORE8 => self.memory.write(&args[0], Some(U256::one()), &args[1].taint),
+            CALLDATACOPY => self.memory.write(&args[0], args[2].as_u256(), &source(TaintSourceKind::Calldata)),
+            RETURNDATACOPY => self.memory.write(&args[0], args[2].as_u256(), &source(TaintSourceKind::ReturnData)),
+            CODECOPY => self.memory.write(&args[0], args[2].as_u256(), &Taint::new()),
+            EXTCODECOPY => self.memory.write(&args[1], args[3].as_u256(), &Taint::new()),
+            CALL | CALLCODE => self.memory.write(&args[5], args[6].as_u256(), &source(TaintSourceKind::ReturnData)),
+            DELEGATECALL | STATICCALL => self.memory.write(&args[4], args[5].as_u256(), &source(TaintSourceKind::ReturnData)),
+            _ => {},
+        }
+        self.stack.execute(op, code);
+        if let Some((offset, size)) = read {
+            let mut top = self.stack.pop();
+            top.taint.extend(self.memory.read(&offset, size));
+            self.stack.push(top);
+        }
+    }
+
+    fn join(&mut self, other: &Self) {
+        self.stack.join(&other.stack);
+        self.memory.join(&other.memory);
+    }
+}
+
+/// Forward taint propagation over the CFG.
+pub type TaintAnalysis = StackAnalysis<TaintState>;
+
+/// Operands of `opcode` that must not be attacker-controlled, by depth.
+fn sinks(opcode: u8) -> &'static [(usize, TaintSinkKind)] {
+    match opcode {
+        JUMP | JUMPI => &[(0, TaintSinkKind::JumpTarget)],
+        SSTORE => &[(0, TaintSinkKind::StorageSlot)],
+        CALL | CALLCODE => &[(1, TaintSinkKind::CallTarget), (2, TaintSinkKind::CallValue)],
+        DELEGATECALL => &[(1, TaintSinkKind::CallTarget)],
+        SELFDESTRUCT => &[(0, TaintSinkKind::SelfdestructBeneficiary)],
+        CREATE2 => &[(3, TaintSinkKind::Create2Salt)],
+        _ => &[],
+    }
+}
+
+/// Every source that can reach a sink operand, once per (source, sink) pair.
+/// Propagation is context-insensitive: paths meeting at a block, including
+/// the callers of a shared internal function, are joined.
+pub fn taint_flows(program: &Program) -> Vec<TaintFlow> {
+    let results: DataFlowResults<Option<TaintState>> = program.solve(&TaintAnalysis::new());
+    let mut pairs = BTreeSet::new();
+    program.block_nodes().for_each(|node| {
+        let ops = &program.cfg[node].ops;
+        results.at_ops(&TaintAnalysis::new(), program, node).into_iter().zip(ops).for_each(|((pc, state), op)| {
+            let state = match state {
+                Some(state) => state,
+                None => return,
+            };
+            sinks(op.code.u8()).iter().for_each(|(depth, kind)| {
+                state.stack.peek_at(*depth).taint.iter().for_each(|source| {
+                    pairs.insert((*source, TaintSink { pc, kind: *kind }));
+                });
+            });
+        });
+    });
+    pairs.into_iter().map(|(source, sink)| TaintFlow {
+        source,
+        sink,
+        path: block_path(program, source.pc, sink.pc),
+    }).collect()
+}
+
+/// Starts of the blocks on a shortest path between the blocks holding `from`
+/// and `to`, both included.
+fn block_path(program: &Program, from: U256, to: U256) -> Vec<U256> {
+    let (start, goal) = match (program.node_containing(from.as_usize()), program.node_containing(to.as_usize())) {
+        (Some(start), Some(goal)) => (start, goal),
+        _ => return vec![],
+    };
+    let mut parents: HashMap<NodeIndex, NodeIndex> = HashMap::new();
+    let mut queue = VecDeque::from([start]);
+    while let Some(node) = queue.pop_front() {
+        if node == goal {
+            break;
+        }
+        program.cfg.neighbors(node)
+            .filter(|next| program.cfg[*next].kind == BlockKind::Code && *next != start)
+            .for_each(|next| {
+                if !parents.contains_key(&next) {
+                    parents.insert(next, node);
+                    queue.push_back(next);
+                }
+            });
+    }
+    let mut path = vec![program.cfg[goal].id()];
+    let mut node = goal;
+    while node != start {
+        node = match parents.get(&node) {
+            Some(parent) => *parent,
+            None => return vec![],
+        };
+        path.push(program.cfg[node].id());
+    }
+    path.reverse();
+    path
+}
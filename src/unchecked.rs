@@ -0,0 +1,132 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use primitive_types::U256;
+use revm::opcode::*;
+use crate::dispatcher::aborts;
+use crate::reentrancy::{UpperBound, CALL_STIPEND};
+use crate::ssa::{Definition, Instruction, Var};
+use crate::stack::AbstractValue;
+use crate::Program;
+
+/// Nesting shown of a call's target expression.
+const TARGET_DEPTH: usize = 3;
+
+/// What became of a call's success flag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum UncheckedKind {
+    /// Dropped without being read or carried across a join.
+    Popped,
+    /// Read, e.g. stored, returned or branched on, but never deciding a
+    /// branch that aborts.
+    NotBranchedOn,
+}
+
+/// A CALL, CALLCODE, DELEGATECALL or STATICCALL whose success decides no
+/// JUMPI with an aborting branch.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct UncheckedCall {
+    pub pc: U256,
+    pub opcode: u8,
+    /// External function holding the call; `None` outside any recovered one.
+    pub selector: Option<[u8; 4]>,
+    /// The address operand as an expression, e.g. `AND(0xff..ff, SLOAD(0x0))`.
+    pub target: String,
+    /// Whether the call forwards no more than the stipend, as `send` does.
+    pub send: bool,
+    pub kind: UncheckedKind,
+}
+
+/// Operations whose result still says whether the call succeeded.
+const FLAG_OPS: [u8; 10] = [ISZERO, EQ, AND, OR, XOR, NOT, LT, GT, SLT, SGT];
+
+/// Follows each call's success flag through the SSA form, across boolean
+/// operations and phis, and reports the calls whose flag never ends up as
+/// the condition of a JUMPI with a successor that reverts. Branching on the
+/// flag only to carry on either way does not count as checking it.
+pub fn unchecked_calls(program: &Program) -> Vec<UncheckedCall> {
+    let ssa = program.ssa();
+    let definitions = ssa.definitions();
+    let mut inst_uses: HashMap<Var, Vec<&Instruction>> = HashMap::new();
+    let mut phi_uses: HashMap<Var, Vec<Var>> = HashMap::new();
+    ssa.blocks.values().for_each(|block| {
+        block.instructions.iter().for_each(|inst| {
+            inst.inputs.iter().for_each(|input| inst_uses.entry(*input).or_default().push(inst));
+        });
+        block.phis.iter().for_each(|phi| {
+            phi.inputs.iter().for_each(|(_, input)| phi_uses.entry(*input).or_default().push(phi.output));
+        });
+    });
+
+    let dispatcher = program.dispatcher();
+    let mut findings = BTreeSet::new();
+    ssa.blocks.values().flat_map(|block| &block.instructions)
+        .filter(|inst| [CALL, CALLCODE, DELEGATECALL, STATICCALL].contains(&inst.opcode))
+        .for_each(|call| {
+            let success = match call.output {
+                Some(success) => success,
+                None => return,
+            };
+            let (mut read, mut checked) = (false, false);
+            let mut seen = HashSet::from([success]);
+            let mut pending = vec![success];
+            while let Some(var) = pending.pop() {
+                inst_uses.get(&var).into_iter().flatten().for_each(|inst| {
+                    read = true;
+                    if inst.opcode == JUMPI && inst.inputs.get(1) == Some(&var) {
+                        checked |= program.node_containing(inst.pc.as_usize())
+                            .map_or(false, |node| program.cfg.neighbors(node).any(|next| aborts(program, next)));
+                    } else if FLAG_OPS.contains(&inst.opcode) {
+                        pending.extend(inst.output.filter(|output| seen.insert(*output)));
+                    }
+                });
+                phi_uses.get(&var).into_iter().flatten().for_each(|output| {
+                    read = true;
+                    if seen.insert(*output) {
+                        pending.push(*output);
+                    }
+                });
+            }
+            if checked {
+                return;
+            }
+
+            let send = call.opcode == CALL && call.inputs.first()
+                .and_then(|gas| upper_bound(program, &definitions, *gas, TARGET_DEPTH))
+                .map_or(false, |gas| gas <= U256::from(CALL_STIPEND));
+            let target = call.inputs.get(1).map_or_else(String::new, |target| ssa.expression_in(&definitions, *target, TARGET_DEPTH));
+            let kind = if read { UncheckedKind::NotBranchedOn } else { UncheckedKind::Popped };
+            let node = program.node_containing(call.pc.as_usize());
+            let selectors = node.map_or(vec![None], |node| dispatcher.selectors_containing(&[node]));
+            selectors.into_iter().for_each(|selector| {
+                findings.insert(UncheckedCall {
+                    pc: call.pc,
+                    opcode: call.opcode,
+                    selector,
+                    target: target.clone(),
+                    send,
+                    kind,
+                });
+            });
+        });
+    findings.into_iter().collect()
+}
+
+/// The `UpperBound` of `var`, evaluated over its definitions.
+fn upper_bound(
+    program: &Program,
+    definitions: &HashMap<Var, Definition<'_>>,
+    var: Var,
+    depth: usize,
+) -> Option<U256> {
+    let inst = match definitions.get(&var) {
+        Some(Definition::Instruction(inst)) => inst,
+        _ => return None,
+    };
+    if let Some(value) = inst.immediate {
+        return Some(value);
+    }
+    let op = program.block_containing(inst.pc.as_usize())?.ops.iter().find(|op| op.pc == Some(inst.pc))?;
+    let args = inst.inputs.iter()
+        .map(|input| UpperBound(depth.checked_sub(1).and_then(|depth| upper_bound(program, definitions, *input, depth))))
+        .collect::<Vec<_>>();
+    UpperBound::transfer(op, &args).0
+}
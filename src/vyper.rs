@@ -0,0 +1,131 @@
+use std::ops::Range;
+use primitive_types::U256;
+use revm::opcode::*;
+use crate::stack::SymbolicStack;
+use crate::{Block, BlockKind, Program};
+
+/// Layout of a Vyper (0.3.10+) selector table in the data section.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum JumpTableKind {
+    /// One 2-byte bucket label per `selector % buckets`; each bucket then
+    /// compares selectors linearly.
+    Sparse,
+    /// 5-byte bucket headers (magic, location, size) pointing at 7-byte
+    /// entries (selector, label, function info).
+    Dense,
+}
+
+/// A selector table read with CODECOPY and jumped through via MLOAD.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JumpTable {
+    pub kind: JumpTableKind,
+    /// Start of the block that copies the bucket header.
+    pub header_block: U256,
+    /// pc of that CODECOPY.
+    pub codecopy_pc: U256,
+    pub buckets: usize,
+    /// Bytes of `Program::code` holding the table.
+    pub data: Range<usize>,
+    /// Every label the table can produce, sorted.
+    pub targets: Vec<U256>,
+    /// Selectors and their entry labels; only dense tables store them.
+    pub selectors: Vec<([u8; 4], U256)>,
+}
+
+/// Finds Vyper selector tables from the code layout alone: a block that
+/// CODECOPYs 2 or 5 bytes from a constant table offset, bucketed by a
+/// constant MOD. Candidate offsets are kept only if every label they decode
+/// to is a JUMPDEST.
+pub fn jump_tables(program: &Program) -> Vec<JumpTable> {
+    program.blocks()
+        .filter(|block| block.kind == BlockKind::Code)
+        .filter_map(|block| jump_table(program, block))
+        .collect()
+}
+
+/// The table `block` reads, if any. Tables are found before any edges
+/// exist, so the block runs on an empty `SymbolicStack` of its own rather
+/// than under `StackAnalysis`.
+fn jump_table(program: &Program, block: &Block) -> Option<JumpTable> {
+    let mut stack = SymbolicStack::new();
+    let mut buckets = None;
+    let mut copy = None;
+    for op in &block.ops {
+        let u8_code = op.code.u8();
+        if u8_code == MOD && buckets.is_none() {
+            buckets = stack.peek_at(1).as_u256();
+        }
+        if u8_code == CODECOPY {
+            copy = stack.peek_at(2).as_u256().zip(op.pc);
+            break;
+        }
+        stack.execute(op, &program.code);
+    }
+    let (size, codecopy_pc) = copy?;
+    let buckets = buckets.filter(|buckets| !buckets.is_zero() && *buckets < U256::from(0x1_0000))?.as_usize();
+    let kind = match size.low_u32() {
+        2 => JumpTableKind::Sparse,
+        5 => JumpTableKind::Dense,
+        _ => return None,
+    };
+    block.ops.iter()
+        .filter(|op| op.arg_size >= 2)
+        .filter_map(|op| op.push_value(&program.code))
+        .filter(|offset| *offset > U256::from(block.pc_end) && *offset < U256::from(program.code.len()))
+        .find_map(|offset| decode(program, kind, offset.as_usize(), buckets))
+        .map(|(data, mut targets, selectors)| {
+            targets.sort();
+            targets.dedup();
+            JumpTable {
+                kind,
+                header_block: block.id(),
+                codecopy_pc,
+                buckets,
+                data,
+                targets,
+                selectors,
+            }
+        })
+}
+
+type Decoded = (Range<usize>, Vec<U256>, Vec<([u8; 4], U256)>);
+
+fn decode(program: &Program, kind: JumpTableKind, offset: usize, buckets: usize) -> Option<Decoded> {
+    let code = &program.code;
+    let read = |at: usize, len: usize| code.get(at..at + len);
+    let label = |at: usize| {
+        let label = U256::from_big_endian(read(at, 2)?);
+        program.jumpdests.get(label.as_usize()).copied().unwrap_or(false).then(|| label)
+    };
+    match kind {
+        JumpTableKind::Sparse => {
+            let targets = (0..buckets).map(|bucket| label(offset + 2 * bucket)).collect::<Option<Vec<_>>>()?;
+            Some((offset..offset + 2 * buckets, targets, vec![]))
+        },
+        JumpTableKind::Dense => {
+            let mut data = offset..offset + 5 * buckets;
+            let mut selectors = vec![];
+            for bucket in 0..buckets {
+                let header = read(offset + 5 * bucket, 5)?;
+                let location = U256::from_big_endian(&header[2..4]).as_usize();
+                let size = header[4] as usize;
+                for entry in 0..size {
+                    let at = location + 7 * entry;
+                    let mut selector = [0u8; 4];
+                    selector.copy_from_slice(read(at, 4)?);
+                    selectors.push((selector, label(at + 4)?));
+                    read(at + 6, 1)?;
+                }
+                if size > 0 {
+                    data.start = data.start.min(location);
+                    data.end = data.end.max(location + 7 * size);
+                }
+            }
+            if selectors.is_empty() {
+                return None;
+            }
+            let targets = selectors.iter().map(|(_, label)| *label).collect();
+            Some((data, targets, selectors))
+        },
+    }
+}